@@ -0,0 +1,278 @@
+/*!
+Character encoding detection for stylesheets
+
+Stylesheets arrive as bytes. Before handing them to libcss we decide which
+encoding to decode them with, considering in order: a byte order mark, an
+`@charset` rule at the very start of the sheet, the charset given by the
+transport layer and finally the encoding of the referring document.
+*/
+
+use types::{EncodingSource, EncodingFromBom, EncodingFromCharsetRule, EncodingFromProtocol,
+            EncodingFromEnvironment, EncodingDefault};
+
+/// The number of bytes examined when looking for an `@charset` rule
+pub const CHARSET_SNIFF_LENGTH: uint = 1024;
+
+/**
+Choose the encoding of a stylesheet that begins with the bytes `prefix`.
+`prefix` should hold at least `CHARSET_SNIFF_LENGTH` bytes unless the sheet
+is shorter than that. Returns the canonical encoding name along with the
+step that selected it.
+*/
+pub fn determine_encoding(prefix: &[u8], protocol: &Option<~str>,
+                          environment: &Option<~str>) -> (~str, EncodingSource) {
+    match bom_encoding(prefix) {
+        Some(encoding) => return (encoding, EncodingFromBom),
+        None => ()
+    }
+
+    match charset_rule_label(prefix).chain(|label| canonical_encoding(label)) {
+        // The rule was read as ASCII, so it can't really be UTF-16
+        Some(~"UTF-16BE") | Some(~"UTF-16LE") => return (~"UTF-8", EncodingFromCharsetRule),
+        Some(encoding) => return (encoding, EncodingFromCharsetRule),
+        None => ()
+    }
+
+    match protocol.chain_ref(|label| canonical_encoding(*label)) {
+        Some(encoding) => return (encoding, EncodingFromProtocol),
+        None => ()
+    }
+
+    match environment.chain_ref(|label| canonical_encoding(*label)) {
+        Some(encoding) => return (encoding, EncodingFromEnvironment),
+        None => ()
+    }
+
+    (~"UTF-8", EncodingDefault)
+}
+
+fn bom_encoding(prefix: &[u8]) -> Option<~str> {
+    if prefix.len() >= 3 && prefix[0] == 0xEF && prefix[1] == 0xBB && prefix[2] == 0xBF {
+        Some(~"UTF-8")
+    } else if prefix.len() >= 2 && prefix[0] == 0xFE && prefix[1] == 0xFF {
+        Some(~"UTF-16BE")
+    } else if prefix.len() >= 2 && prefix[0] == 0xFF && prefix[1] == 0xFE {
+        Some(~"UTF-16LE")
+    } else {
+        None
+    }
+}
+
+/**
+Extract the label from an `@charset "label";` rule. The rule only counts if
+it is spelled exactly so, byte for byte, at the very start of the sheet.
+*/
+fn charset_rule_label(prefix: &[u8]) -> Option<~str> {
+    let start = str::to_bytes("@charset \"");
+    if !vec::starts_with(prefix, start) {
+        return None;
+    }
+
+    let mut label = ~"";
+    let mut i = start.len();
+    while i + 1 < prefix.len() && i < CHARSET_SNIFF_LENGTH {
+        let b = prefix[i];
+        if b == '"' as u8 {
+            if prefix[i + 1] == ';' as u8 {
+                return Some(label);
+            }
+            return None;
+        }
+        if b >= 0x80 {
+            return None;
+        }
+        str::push_char(&mut label, b as char);
+        i += 1;
+    }
+    None
+}
+
+/**
+Map an encoding label, e.g. `latin1` or `Shift-JIS`, to the name libcss
+knows the encoding by. Returns `None` for unsupported labels.
+*/
+pub fn canonical_encoding(label: &str) -> Option<~str> {
+    let label = label.trim().to_lower();
+
+    for encoding_labels().each |&(name, labels)| {
+        for labels.each |&candidate| {
+            if str::eq_slice(candidate, label) {
+                return Some(name.to_owned());
+            }
+        }
+    }
+
+    // The ISO-8859 and windows code pages are spelled too many ways to list
+    for [~"iso-8859-", ~"iso8859-", ~"iso_8859-", ~"iso8859"].each |prefix| {
+        if label.starts_with(*prefix) {
+            match uint::from_str(label.slice(prefix.len(), label.len())) {
+                Some(part) if part >= 2 && part <= 15 && part != 12 => {
+                    return Some(fmt!("ISO-8859-%u", part));
+                }
+                _ => ()
+            }
+        }
+    }
+    for [~"windows-", ~"cp", ~"x-cp"].each |prefix| {
+        if label.starts_with(*prefix) {
+            match uint::from_str(label.slice(prefix.len(), label.len())) {
+                Some(page) if page >= 1250 && page <= 1258 => {
+                    return Some(fmt!("windows-%u", page));
+                }
+                _ => ()
+            }
+        }
+    }
+
+    None
+}
+
+fn encoding_labels() -> ~[(&'static str, ~[&'static str])] {
+    ~[
+        ("UTF-8", ~["utf-8", "utf8", "unicode-1-1-utf-8"]),
+        ("UTF-16BE", ~["utf-16be"]),
+        ("UTF-16LE", ~["utf-16le", "utf-16"]),
+        ("US-ASCII", ~["us-ascii", "ascii", "ansi_x3.4-1968"]),
+        ("ISO-8859-1", ~["iso-8859-1", "iso8859-1", "iso_8859-1", "iso88591", "latin1", "l1",
+                         "cp819", "ibm819", "csisolatin1"]),
+        ("Shift_JIS", ~["shift_jis", "shift-jis", "sjis", "ms_kanji", "csshiftjis",
+                        "windows-31j", "x-sjis"]),
+        ("EUC-JP", ~["euc-jp", "cseucpkdfmtjapanese", "x-euc-jp"]),
+        ("ISO-2022-JP", ~["iso-2022-jp", "csiso2022jp"]),
+        ("GBK", ~["gbk", "gb2312", "chinese", "csgb2312", "x-gbk"]),
+        ("GB18030", ~["gb18030"]),
+        ("Big5", ~["big5", "big5-hkscs", "cn-big5", "csbig5", "x-x-big5"]),
+        ("EUC-KR", ~["euc-kr", "cseuckr", "ks_c_5601-1987", "windows-949"]),
+        ("KOI8-R", ~["koi8-r", "koi8", "cskoi8r"]),
+        ("KOI8-U", ~["koi8-u"]),
+        ("macintosh", ~["macintosh", "mac", "csmacintosh"])
+    ]
+}
+
+/**
+Decode a stylesheet for our own pass over it. libcss does the decoding that
+feeds the cascade; this handles the Unicode encodings, US-ASCII, ISO-8859-1
+and windows-1252, and returns `None` for the rest rather than guess. Any BOM
+is dropped.
+*/
+pub fn decode(bytes: &[u8], encoding: &str) -> Option<~str> {
+    match encoding {
+        "UTF-8" => Some(decode_utf8(bytes)),
+        "UTF-16LE" => Some(decode_utf16(bytes, false)),
+        "UTF-16BE" => Some(decode_utf16(bytes, true)),
+        "US-ASCII" => {
+            Some(decode_single_byte(bytes, |b| if b < 0x80 { b as char } else { '\uFFFD' }))
+        }
+        "ISO-8859-1" => Some(decode_single_byte(bytes, |b| b as char)),
+        "windows-1252" => Some(decode_single_byte(bytes, windows_1252_char)),
+        _ => None
+    }
+}
+
+fn decode_single_byte(bytes: &[u8], f: &fn(u8) -> char) -> ~str {
+    let mut result = ~"";
+    str::reserve(&mut result, bytes.len());
+    for bytes.each |&b| {
+        str::push_char(&mut result, f(b));
+    }
+    result
+}
+
+fn decode_utf8(bytes: &[u8]) -> ~str {
+    let bytes = if vec::starts_with(bytes, [0xEF, 0xBB, 0xBF]) {
+        bytes.slice(3, bytes.len())
+    } else {
+        bytes
+    };
+
+    let mut result = ~"";
+    str::reserve(&mut result, bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i] as uint;
+        let (len, min, initial) = if b < 0x80 {
+            (1, 0, b)
+        } else if b & 0xE0 == 0xC0 {
+            (2, 0x80, b & 0x1F)
+        } else if b & 0xF0 == 0xE0 {
+            (3, 0x800, b & 0x0F)
+        } else if b & 0xF8 == 0xF0 {
+            (4, 0x10000, b & 0x07)
+        } else {
+            (0, 0, 0)
+        };
+
+        let mut code = initial;
+        let mut valid = len > 0 && i + len <= bytes.len();
+        if valid {
+            for uint::range(1, len) |j| {
+                let continuation = bytes[i + j] as uint;
+                if continuation & 0xC0 != 0x80 {
+                    valid = false;
+                    break;
+                }
+                code = (code << 6) | (continuation & 0x3F);
+            }
+        }
+        // Reject overlong forms, surrogates and out of range code points
+        if valid && (code < min || code > 0x10FFFF || (code >= 0xD800 && code <= 0xDFFF)) {
+            valid = false;
+        }
+
+        if valid {
+            str::push_char(&mut result, code as char);
+            i += len;
+        } else {
+            str::push_char(&mut result, '\uFFFD');
+            i += 1;
+        }
+    }
+    result
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> ~str {
+    let mut units = ~[];
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        let (hi, lo) = if big_endian { (bytes[i], bytes[i + 1]) } else { (bytes[i + 1], bytes[i]) };
+        units.push(((hi as u16) << 8) | (lo as u16));
+        i += 2;
+    }
+    if !units.is_empty() && units[0] == 0xFEFF {
+        units.shift();
+    }
+
+    let mut result = ~"";
+    let mut j = 0;
+    while j < units.len() {
+        let unit = units[j] as uint;
+        if unit >= 0xD800 && unit <= 0xDBFF && j + 1 < units.len() &&
+                units[j + 1] as uint >= 0xDC00 && units[j + 1] as uint <= 0xDFFF {
+            let low = units[j + 1] as uint;
+            str::push_char(&mut result, (0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)) as char);
+            j += 2;
+        } else if unit >= 0xD800 && unit <= 0xDFFF {
+            str::push_char(&mut result, '\uFFFD');
+            j += 1;
+        } else {
+            str::push_char(&mut result, unit as char);
+            j += 1;
+        }
+    }
+    result
+}
+
+/** windows-1252 differs from ISO-8859-1 only in 0x80 - 0x9F */
+fn windows_1252_char(b: u8) -> char {
+    let table = [
+        '\u20AC', '\x81', '\u201A', '\u0192', '\u201E', '\u2026', '\u2020', '\u2021',
+        '\u02C6', '\u2030', '\u0160', '\u2039', '\u0152', '\x8D', '\u017D', '\x8F',
+        '\x90', '\u2018', '\u2019', '\u201C', '\u201D', '\u2022', '\u2013', '\u2014',
+        '\u02DC', '\u2122', '\u0161', '\u203A', '\u0153', '\x9D', '\u017E', '\u0178'
+    ];
+    if b >= 0x80 && b <= 0x9F {
+        table[(b - 0x80) as uint]
+    } else {
+        b as char
+    }
+}
@@ -1,77 +1,119 @@
-use color::Color;
+use color::{Color, css_colors};
 use select::SelectResults;
-use computed::ComputedStyle;
-use n::h::CssHintLength;
-use n::u::float_to_css_fixed;
+use computed::{ComputedStyle, convert_net_color_value};
+use n::h::{CssHint, CssHintLength, CssHintFontSize};
+use n::u::{css_fixed_to_float, float_to_css_fixed};
+use units::{AbsoluteSize, XXSmall, XSmall, Small, Medium, Large, XLarge, XXLarge};
+use types::{PseudoElement, PseudoElementFirstLine, PseudoElementFirstLetter};
+use types::{PseudoElementBefore, PseudoElementAfter};
 use values::*;
 use n;
 
 pub struct CompleteSelectResults {
-    inner: SelectResults
+    inner: SelectResults,
+    /// The element's color, resolved against its parent's
+    color: Color
 }
 
 pub impl CompleteSelectResults {
     static fn new_root(root: SelectResults) -> CompleteSelectResults {
+        CompleteSelectResults::new_root_with_metrics(root, @DefaultFontMetrics as
+                                                     @FontMetricsProvider)
+    }
+
+    static fn new_root_with_metrics(root: SelectResults,
+                                    metrics: @FontMetricsProvider) -> CompleteSelectResults {
+        compose_pseudo_styles(&root, metrics);
+        // The root inherits the initial color
+        let color = resolve_color(&root.computed_style(), css_colors::black());
         CompleteSelectResults {
-            inner: root
+            inner: root,
+            color: color
         }
     }
 
+    /**
+    Compose `child` with the style of its parent, resolving relative font
+    sizes against the default font metrics
+    */
     static fn new_from_parent(parent: &CompleteSelectResults,
                               child: SelectResults) -> CompleteSelectResults {
-        let mut child = child;
-
-        // New lifetime
-        {
-            let parent_computed = parent.computed_style();
-            let mut child_computed = child.computed_style();
-            //let net_parent_computed = &parent_computed.inner.inner;
-            let net_child_computed = &/*mut*/ child_computed.inner;
-            // FIXME: Need to get real font sizes
-            let cb: n::c::ComputeFontSizeCb =
-                |parent: &Option<n::h::CssHint>, child: &n::h::CssHint| -> n::h::CssHint {
-                match *child {
-                    CssHintLength(n::t::CssUnitEm(child_em)) => {
-                        match *parent {
-                            Some(CssHintLength(parent_unit)) => {
-                                // CSS3 Values 5.1.1: Multiply parent unit by child unit.
-                                let mut new_value =
-                                    n::u::css_fixed_to_float(parent_unit.to_css_fixed());
-                                new_value *= n::u::css_fixed_to_float(child_em);
-                                let unit = parent_unit.modify(n::u::float_to_css_fixed(
-                                    new_value));
-                                CssHintLength(unit)
-                            }
-                            _ => n::h::CssHintLength(n::t::CssUnitEm(child_em)),
-                        }
-                    }
-                    CssHintLength(unit) => CssHintLength(unit),
-                    _ => {
-                        n::h::CssHintLength(n::t::CssUnitPx(float_to_css_fixed(16.0)))
-                    }
-                }
-            };
-            // XXX: Need an aliasable &mut here
-            let net_result_computed: &mut n::c::CssComputedStyle = unsafe { cast::transmute(net_child_computed) };
-            let net_child_computed: &mut n::c::CssComputedStyle = unsafe { cast::transmute(&child_computed.inner) };
-            let net_parent_computed = &parent_computed.inner.inner;
-            n::c::compose(net_parent_computed, net_child_computed, cb, net_result_computed);
-        }
+        CompleteSelectResults::new_from_parent_with_metrics(parent, child,
+                                                            @DefaultFontMetrics as
+                                                            @FontMetricsProvider)
+    }
 
+    static fn new_from_parent_with_metrics(parent: &CompleteSelectResults,
+                                           child: SelectResults,
+                                           metrics: @FontMetricsProvider)
+                                        -> CompleteSelectResults {
+        compose(&parent.computed_style().inner, &child.computed_style(), metrics);
+        compose_pseudo_styles(&child, metrics);
+        let color = resolve_color(&child.computed_style(), parent.color);
         CompleteSelectResults {
-            inner: child
+            inner: child,
+            color: color
         }
     }
 
     fn computed_style(&self) -> CompleteStyle/&self {
         CompleteStyle {
-            inner: self.inner.computed_style()
+            inner: self.inner.computed_style(),
+            color: self.color
         }
     }
+
+    /**
+    The style of one of the element's pseudo-elements, composed with the
+    element's own style, or `None` if no rule applies to it
+    */
+    fn pseudo_style(&self, pseudo_element: PseudoElement) -> Option<CompleteStyle/&self> {
+        match self.inner.pseudo_style(pseudo_element) {
+            Some(style) => {
+                let color = resolve_color(&style, self.color);
+                Some(CompleteStyle { inner: style, color: color })
+            }
+            None => None
+        }
+    }
+}
+
+/** The color of `style`, or `parent_color` if it inherits it */
+fn resolve_color(style: &ComputedStyle, parent_color: Color) -> Color {
+    match style.color() {
+        Specified(color) => color,
+        Inherit => parent_color
+    }
+}
+
+/** Pseudo-elements inherit from the element they belong to */
+fn compose_pseudo_styles(results: &SelectResults, metrics: @FontMetricsProvider) {
+    let element = results.computed_style();
+    for [PseudoElementFirstLine, PseudoElementFirstLetter,
+         PseudoElementBefore, PseudoElementAfter].each |&pseudo_element| {
+        for results.pseudo_style(pseudo_element).each |style| {
+            compose(&element, style, metrics);
+        }
+    }
+}
+
+/** Fill in the inherited values of `child`, in place, from `parent` */
+fn compose(parent: &ComputedStyle, child: &ComputedStyle, metrics: @FontMetricsProvider) {
+    let cb: n::c::ComputeFontSizeCb =
+        |parent: &Option<n::h::CssHint>, child: &n::h::CssHint| -> n::h::CssHint {
+        compute_font_size(metrics, parent, child)
+    };
+    // XXX: Need an aliasable &mut here
+    let net_result_computed: &mut n::c::CssComputedStyle = unsafe { cast::transmute(&child.inner) };
+    let net_child_computed: &mut n::c::CssComputedStyle = unsafe { cast::transmute(&child.inner) };
+    let net_parent_computed = &parent.inner;
+    n::c::compose(net_parent_computed, net_child_computed, cb, net_result_computed);
 }
 
 pub struct CompleteStyle {
-    inner: ComputedStyle<'self>
+    inner: ComputedStyle<'self>,
+    /// What `currentColor` stands for
+    color: Color
 }
 
 impl CompleteStyle<'self> {
@@ -111,23 +153,23 @@ impl CompleteStyle<'self> {
     }
 
     pub fn border_top_color(&self) -> Color {
-        strip(self.inner.border_top_color())
+        self.resolve_color_value(self.inner.inner.border_top_color())
     }
 
     pub fn border_top_color(&self) -> Color {
-        strip(self.inner.border_top_color())
+        self.resolve_color_value(self.inner.inner.border_top_color())
     }
 
     pub fn border_right_color(&self) -> Color {
-        strip(self.inner.border_right_color())
+        self.resolve_color_value(self.inner.inner.border_right_color())
     }
 
     pub fn border_bottom_color(&self) -> Color {
-        strip(self.inner.border_bottom_color())
+        self.resolve_color_value(self.inner.inner.border_bottom_color())
     }
 
     pub fn border_left_color(&self) -> Color {
-        strip(self.inner.border_left_color())
+        self.resolve_color_value(self.inner.inner.border_left_color())
     }
 
     // CSS 2.1, Section 9 - Visual formatting model
@@ -164,14 +206,34 @@ impl CompleteStyle<'self> {
 
     // CSS 2.1, Section 13 - Paged media
 
+    pub fn page_break_before(&self) -> CSSPageBreak {
+        strip(self.inner.page_break_before())
+    }
+
+    pub fn page_break_after(&self) -> CSSPageBreak {
+        strip(self.inner.page_break_after())
+    }
+
+    pub fn page_break_inside(&self) -> CSSPageBreakInside {
+        strip(self.inner.page_break_inside())
+    }
+
+    pub fn orphans(&self) -> uint {
+        strip(self.inner.orphans())
+    }
+
+    pub fn widows(&self) -> uint {
+        strip(self.inner.widows())
+    }
+
     // CSS 2.1, Section 14 - Colors and Backgrounds
 
     pub fn background_color(&self) -> Color {
-        strip(self.inner.background_color())
+        self.resolve_color_value(self.inner.inner.background_color())
     }
 
     pub fn color(&self) -> Color {
-        strip(self.inner.color())
+        self.color
     }
 
     // CSS 2.1, Section 15 - Fonts
@@ -202,6 +264,9 @@ impl CompleteStyle<'self> {
 
     // CSS 2.1, Section 18 - User interface
 
+    priv fn resolve_color_value(&self, color: n::v::CssColorValue) -> Color {
+        strip(convert_net_color_value(color, Specified(self.color)))
+    }
 }
 
 fn strip<T>(value: CSSValue<T>) -> T {
@@ -211,3 +276,140 @@ fn strip<T>(value: CSSValue<T>) -> T {
     }
 }
 
+
+/**
+The font sizes a host uses to compute `font-size`, CSS 2.1 section 15.7.
+Sizes are in px.
+*/
+pub trait FontMetricsProvider {
+    /// The size of `medium`, the initial font size
+    fn medium_size(&self) -> float;
+    /// The size each absolute-size keyword maps to
+    fn absolute_size(&self, size: AbsoluteSize) -> float;
+    /// The factor between adjacent absolute sizes, used for `larger` and
+    /// `smaller` when the parent's size isn't in the table
+    fn scale_ratio(&self) -> float;
+    /// The x-height as a fraction of the font size, for `ex` units
+    fn x_height_ratio(&self) -> float;
+}
+
+/** A 16px `medium`, with the scaling factors suggested by CSS 3 Fonts */
+pub struct DefaultFontMetrics;
+
+impl FontMetricsProvider for DefaultFontMetrics {
+    fn medium_size(&self) -> float { 16.0 }
+
+    fn absolute_size(&self, size: AbsoluteSize) -> float {
+        let factor = match size {
+            XXSmall => 3.0 / 5.0,
+            XSmall => 3.0 / 4.0,
+            Small => 8.0 / 9.0,
+            Medium => 1.0,
+            Large => 6.0 / 5.0,
+            XLarge => 3.0 / 2.0,
+            XXLarge => 2.0
+        };
+        self.medium_size() * factor
+    }
+
+    fn scale_ratio(&self) -> float { 1.2 }
+
+    fn x_height_ratio(&self) -> float { 0.5 }
+}
+
+const ABSOLUTE_SIZES: &'static [AbsoluteSize] = &[
+    XXSmall, XSmall, Small, Medium, Large, XLarge, XXLarge
+];
+
+/**
+Compute the font size hinted by `child`, given the computed size of its
+parent, to an absolute length
+*/
+fn compute_font_size(metrics: @FontMetricsProvider, parent: &Option<CssHint>,
+                     child: &CssHint) -> CssHint {
+    match *child {
+        CssHintLength(n::t::CssUnitEm(em)) => {
+            CssHintLength(scale(parent_font_size(metrics, parent), css_fixed_to_float(em)))
+        }
+        CssHintLength(n::t::CssUnitEx(ex)) => {
+            let factor = css_fixed_to_float(ex) * metrics.x_height_ratio();
+            CssHintLength(scale(parent_font_size(metrics, parent), factor))
+        }
+        CssHintLength(n::t::CssUnitPct(pct)) => {
+            let factor = css_fixed_to_float(pct) / 100.0;
+            CssHintLength(scale(parent_font_size(metrics, parent), factor))
+        }
+        CssHintLength(unit) => CssHintLength(unit),
+        CssHintFontSize(n::v::CssFontSizeLarger) => {
+            CssHintLength(step_font_size(metrics, parent_font_size(metrics, parent), true))
+        }
+        CssHintFontSize(n::v::CssFontSizeSmaller) => {
+            CssHintLength(step_font_size(metrics, parent_font_size(metrics, parent), false))
+        }
+        CssHintFontSize(size) => {
+            match net_absolute_size(size) {
+                Some(size) => px(metrics.absolute_size(size)),
+                None => px(metrics.medium_size())
+            }
+        }
+        _ => px(metrics.medium_size())
+    }
+}
+
+/** The parent's size as an absolute length. The root's parent is `medium` */
+fn parent_font_size(metrics: @FontMetricsProvider, parent: &Option<CssHint>) -> n::t::CssUnit {
+    match *parent {
+        None => n::t::CssUnitPx(float_to_css_fixed(metrics.medium_size())),
+        Some(ref parent) => {
+            match compute_font_size(metrics, &None, parent) {
+                CssHintLength(unit) => unit,
+                _ => n::t::CssUnitPx(float_to_css_fixed(metrics.medium_size()))
+            }
+        }
+    }
+}
+
+/**
+`larger` and `smaller`: a size in the absolute-size table moves to the
+next entry, anything else is scaled by the ratio between entries
+*/
+fn step_font_size(metrics: @FontMetricsProvider, parent: n::t::CssUnit,
+                  larger: bool) -> n::t::CssUnit {
+    let ratio = if larger { metrics.scale_ratio() } else { 1.0 / metrics.scale_ratio() };
+    let parent_px = match parent {
+        n::t::CssUnitPx(value) => css_fixed_to_float(value),
+        _ => return scale(parent, ratio)
+    };
+    match ABSOLUTE_SIZES.position(|&size| float::abs(metrics.absolute_size(size) - parent_px) < 0.5) {
+        Some(i) if larger && i + 1 < ABSOLUTE_SIZES.len() => {
+            n::t::CssUnitPx(float_to_css_fixed(metrics.absolute_size(ABSOLUTE_SIZES[i + 1])))
+        }
+        Some(i) if !larger && i > 0 => {
+            n::t::CssUnitPx(float_to_css_fixed(metrics.absolute_size(ABSOLUTE_SIZES[i - 1])))
+        }
+        _ => scale(parent, ratio)
+    }
+}
+
+/** CSS3 Values 5.1.1: multiply the parent unit by the child's factor */
+fn scale(unit: n::t::CssUnit, factor: float) -> n::t::CssUnit {
+    let value = css_fixed_to_float(unit.to_css_fixed()) * factor;
+    unit.modify(float_to_css_fixed(value))
+}
+
+fn px(value: float) -> CssHint {
+    CssHintLength(n::t::CssUnitPx(float_to_css_fixed(value)))
+}
+
+fn net_absolute_size(size: n::v::CssFontSizeValue) -> Option<AbsoluteSize> {
+    match size {
+        n::v::CssFontSizeXXSmall => Some(XXSmall),
+        n::v::CssFontSizeXSmall => Some(XSmall),
+        n::v::CssFontSizeSmall => Some(Small),
+        n::v::CssFontSizeMedium => Some(Medium),
+        n::v::CssFontSizeLarge => Some(Large),
+        n::v::CssFontSizeXLarge => Some(XLarge),
+        n::v::CssFontSizeXXLarge => Some(XXLarge),
+        _ => None
+    }
+}
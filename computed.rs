@@ -1,5 +1,5 @@
 use color::{Color, rgba};
-use units::{Length, Px, Em, Pt};
+use units::{Length, Px, Em, Ex, Pt};
 use netsurfcss::util::css_fixed_to_float;
 use core::either::{Either, Left, Right};
 use n;
@@ -46,23 +46,23 @@ pub impl<'self> ComputedStyle<'self> {
     }
 
     pub fn border_top_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.border_top_color())
+        convert_net_color_value(self.inner.border_top_color(), self.color())
     }
 
     pub fn border_top_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.border_top_color())
+        convert_net_color_value(self.inner.border_top_color(), self.color())
     }
 
     pub fn border_right_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.border_right_color())
+        convert_net_color_value(self.inner.border_right_color(), self.color())
     }
 
     pub fn border_bottom_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.border_bottom_color())
+        convert_net_color_value(self.inner.border_bottom_color(), self.color())
     }
 
     pub fn border_left_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.border_left_color())
+        convert_net_color_value(self.inner.border_left_color(), self.color())
     }
 
     // CSS 2.1, Section 9 - Visual formatting model
@@ -99,14 +99,35 @@ pub impl<'self> ComputedStyle<'self> {
 
     // CSS 2.1, Section 13 - Paged media
 
+    pub fn page_break_before(&self) -> CSSValue<CSSPageBreak> {
+        convert_net_page_break_value(self.inner.page_break_before())
+    }
+
+    pub fn page_break_after(&self) -> CSSValue<CSSPageBreak> {
+        convert_net_page_break_value(self.inner.page_break_after())
+    }
+
+    pub fn page_break_inside(&self) -> CSSValue<CSSPageBreakInside> {
+        convert_net_page_break_inside_value(self.inner.page_break_inside())
+    }
+
+    pub fn orphans(&self) -> CSSValue<uint> {
+        convert_net_orphans_value(self.inner.orphans())
+    }
+
+    pub fn widows(&self) -> CSSValue<uint> {
+        convert_net_widows_value(self.inner.widows())
+    }
+
     // CSS 2.1, Section 14 - Colors and Backgrounds
 
     pub fn background_color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.background_color())
+        convert_net_color_value(self.inner.background_color(), self.color())
     }
 
+    /// `currentColor` on `color` itself means the parent's color
     pub fn color(&self) -> CSSValue<Color> {
-        convert_net_color_value(self.inner.color())
+        convert_net_color_value(self.inner.color(), Inherit)
     }
 
     // CSS 2.1, Section 15 - Fonts
@@ -143,10 +164,16 @@ fn convert_net_color(color: n::t::CssColor) -> Color {
     rgba(color.r, color.g, color.b, (color.a as float) / 255.0)
 }
 
-fn convert_net_color_value(color: n::v::CssColorValue) -> CSSValue<Color> {
+/**
+`current_color` is what `currentColor` resolves to, the element's `color`.
+That is `Inherit` until `CompleteStyle` has composed it with the parent's.
+*/
+pub fn convert_net_color_value(color: n::v::CssColorValue,
+                               current_color: CSSValue<Color>) -> CSSValue<Color> {
     match color {
         n::v::CssColorInherit => Inherit,
-        n::v::CssColorColor(v) => Specified(convert_net_color(v))
+        n::v::CssColorColor(v) => Specified(convert_net_color(v)),
+        n::v::CssColorCurrentColor => current_color
     }
 }
 
@@ -225,6 +252,39 @@ fn convert_net_position_value(value: n::v::CssPositionValue) -> CSSValue<CSSPosi
     }
 }
 
+fn convert_net_page_break_value(value: n::v::CssPageBreakValue) -> CSSValue<CSSPageBreak> {
+    match value {
+        n::v::CssPageBreakInherit => Inherit,
+        n::v::CssPageBreakAuto => Specified(CSSPageBreakAuto),
+        n::v::CssPageBreakAlways => Specified(CSSPageBreakAlways),
+        n::v::CssPageBreakAvoid => Specified(CSSPageBreakAvoid),
+        n::v::CssPageBreakLeft => Specified(CSSPageBreakLeft),
+        n::v::CssPageBreakRight => Specified(CSSPageBreakRight)
+    }
+}
+
+fn convert_net_page_break_inside_value(value: n::v::CssPageBreakInsideValue) -> CSSValue<CSSPageBreakInside> {
+    match value {
+        n::v::CssPageBreakInsideInherit => Inherit,
+        n::v::CssPageBreakInsideAuto => Specified(CSSPageBreakInsideAuto),
+        n::v::CssPageBreakInsideAvoid => Specified(CSSPageBreakInsideAvoid)
+    }
+}
+
+fn convert_net_orphans_value(value: n::v::CssOrphansValue) -> CSSValue<uint> {
+    match value {
+        n::v::CssOrphansInherit => Inherit,
+        n::v::CssOrphansSet(count) => Specified(count as uint)
+    }
+}
+
+fn convert_net_widows_value(value: n::v::CssWidowsValue) -> CSSValue<uint> {
+    match value {
+        n::v::CssWidowsInherit => Inherit,
+        n::v::CssWidowsSet(count) => Specified(count as uint)
+    }
+}
+
 fn convert_net_font_family_value(value: n::v::CssFontFamilyValue) -> CSSValue<~[CSSFontFamily]> {
     use units::{Serif, SansSerif, Cursive, Fantasy, Monospace};
 
@@ -330,6 +390,7 @@ fn convert_net_unit_to_length_or_percent(unit: n::t::CssUnit) -> Either<Length,
     match unit {
         n::t::CssUnitPx(l) => Left(Px(css_fixed_to_float(l))),
         n::t::CssUnitEm(l) => Left(Em(css_fixed_to_float(l))),
+        n::t::CssUnitEx(l) => Left(Ex(css_fixed_to_float(l))),
         n::t::CssUnitPt(l) => Left(Pt(css_fixed_to_float(l))),
         n::t::CssUnitPct(p) => Right(css_fixed_to_float(p)),
         _ => unimpl("unit")
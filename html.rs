@@ -0,0 +1,283 @@
+/*!
+The default style of HTML, after the sample style sheet of CSS 2.1 appendix D,
+and the presentational hints of its legacy attributes
+*/
+
+use std::net::url::Url;
+use url_from_str = std::net::url::from_str;
+use stylesheet::Stylesheet;
+use color::Color;
+use parse_color_keyword = color::parsing::parse_color;
+use values::*;
+use units::{Length, Px, AbsoluteSize, XSmall, Small, Medium, Large, XLarge, XXLarge};
+use properties::*;
+
+/// Where rules of the HTML user agent sheet claim to come from
+const HTML_UA_URL: &'static str = "about:html-ua.css";
+
+const HTML_UA_CSS: &'static str = "\
+html, address, blockquote, body, dd, div, dl, dt, fieldset, form, frame, frameset,
+h1, h2, h3, h4, h5, h6, noframes, ol, p, ul, center, dir, hr, menu, pre {
+    display: block
+}
+li { display: list-item }
+head, script, style, title, base, link, meta { display: none }
+table { display: table }
+tr { display: table-row }
+thead { display: table-header-group }
+tbody { display: table-row-group }
+tfoot { display: table-footer-group }
+col { display: table-column }
+colgroup { display: table-column-group }
+td, th { display: table-cell }
+caption { display: table-caption }
+th { font-weight: bolder; text-align: center }
+caption { text-align: center }
+body { margin: 8px }
+h1 { font-size: 2em; margin: .67em 0 }
+h2 { font-size: 1.5em; margin: .75em 0 }
+h3 { font-size: 1.17em; margin: .83em 0 }
+h4, p, blockquote, ul, fieldset, form, ol, dl, dir, menu { margin: 1.12em 0 }
+h5 { font-size: .83em; margin: 1.5em 0 }
+h6 { font-size: .75em; margin: 1.67em 0 }
+h1, h2, h3, h4, h5, h6, b, strong { font-weight: bolder }
+blockquote { margin-left: 40px; margin-right: 40px }
+i, cite, em, var, address { font-style: italic }
+pre, tt, code, kbd, samp { font-family: monospace }
+pre { white-space: pre }
+button, textarea, input, select { display: inline-block }
+big { font-size: 1.17em }
+small, sub, sup { font-size: .83em }
+sub { vertical-align: sub }
+sup { vertical-align: super }
+table { border-spacing: 2px }
+thead, tbody, tfoot { vertical-align: middle }
+td, th, tr { vertical-align: inherit }
+s, strike, del { text-decoration: line-through }
+hr { border: 1px inset }
+ol, ul, dir, menu, dd { margin-left: 40px }
+ol { list-style-type: decimal }
+ol ul, ul ol, ul ul, ol ol { margin-top: 0; margin-bottom: 0 }
+u, ins { text-decoration: underline }
+br:before { content: \"\\A\"; white-space: pre-line }
+center { text-align: center }
+:link, :visited { text-decoration: underline }
+:focus { outline: thin dotted invert }
+
+/* Begin bidirectionality settings (do not change) */
+bdo[dir=\"ltr\"] { direction: ltr; unicode-bidi: bidi-override }
+bdo[dir=\"rtl\"] { direction: rtl; unicode-bidi: bidi-override }
+
+*[dir=\"ltr\"] { direction: ltr; unicode-bidi: embed }
+*[dir=\"rtl\"] { direction: rtl; unicode-bidi: embed }
+
+@media print {
+    h1 { page-break-before: always }
+    h1, h2, h3, h4, h5, h6 { page-break-after: avoid }
+    ul, ol, dl { page-break-before: avoid }
+}
+";
+
+/** Parse the default HTML sheet, to be appended with `OriginUA` */
+pub fn html_ua_stylesheet() -> Stylesheet {
+    Stylesheet::from_str(html_ua_url(), HTML_UA_CSS)
+}
+
+fn html_ua_url() -> Url {
+    result::unwrap(url_from_str(HTML_UA_URL))
+}
+
+/**
+The presentational hints of the common HTML4 attributes of an element called
+`name`, for `SelectHandler::node_presentational_hints`. `attr` looks up an
+attribute of the element. `cellpadding` is asked for on `td` and `th`, and
+should be answered from the cell's table.
+*/
+pub fn html_presentational_hints(name: &str, attr: &fn(&str) -> Option<~str>) -> ~[PropertyValue] {
+    let name = name.to_lower();
+    let mut hints = ~[];
+
+    match name {
+        ~"body" | ~"table" | ~"tr" | ~"td" | ~"th" => {
+            for attr("bgcolor").chain(legacy_color).each |&color| {
+                hints.push(BackgroundColorValue(Specified(CSSBackgroundColorColor(color))));
+            }
+        }
+        _ => ()
+    }
+    if name == ~"body" {
+        for attr("text").chain(legacy_color).each |&color| {
+            hints.push(ColorValue(Specified(CSSColorColor(color))));
+        }
+    }
+    match name {
+        ~"table" | ~"td" | ~"th" | ~"col" | ~"colgroup" | ~"img" | ~"hr" | ~"iframe" |
+        ~"object" => {
+            for attr("width").chain(legacy_length).each |&(width, percentage)| {
+                hints.push(WidthValue(Specified(if percentage {
+                    CSSWidthPercentage(width)
+                } else {
+                    CSSWidthLength(Px(width))
+                })));
+            }
+        }
+        _ => ()
+    }
+    match name {
+        ~"table" | ~"tr" | ~"td" | ~"th" | ~"img" | ~"iframe" | ~"object" => {
+            for attr("height").chain(legacy_length).each |&(height, percentage)| {
+                hints.push(HeightValue(Specified(if percentage {
+                    CSSHeightPercentage(height)
+                } else {
+                    CSSHeightLength(Px(height))
+                })));
+            }
+        }
+        _ => ()
+    }
+    match name {
+        // Replaced elements and tables float to the side they are aligned to
+        ~"img" | ~"object" | ~"table" => {
+            match attr("align").map(|align| align.to_lower()) {
+                Some(~"left") => hints.push(FloatValue(Specified(CSSFloatLeft))),
+                Some(~"right") => hints.push(FloatValue(Specified(CSSFloatRight))),
+                _ => ()
+            }
+        }
+        ~"div" | ~"p" | ~"h1" | ~"h2" | ~"h3" | ~"h4" | ~"h5" | ~"h6" | ~"caption" |
+        ~"thead" | ~"tbody" | ~"tfoot" | ~"tr" | ~"td" | ~"th" => {
+            let align = match attr("align").map(|align| align.to_lower()) {
+                Some(~"left") => Some(CSSTextAlignLeft),
+                Some(~"right") => Some(CSSTextAlignRight),
+                Some(~"center") | Some(~"middle") => Some(CSSTextAlignCenter),
+                Some(~"justify") => Some(CSSTextAlignJustify),
+                _ => None
+            };
+            for align.each |&align| {
+                hints.push(TextAlignValue(Specified(align)));
+            }
+        }
+        _ => ()
+    }
+    match name {
+        ~"table" | ~"img" | ~"object" => {
+            for attr("border").chain(legacy_pixels).each |&width| {
+                let width = Specified(CSSBorderWidthLength(width));
+                hints.push_all([BorderTopWidthValue(width), BorderRightWidthValue(width),
+                                BorderBottomWidthValue(width), BorderLeftWidthValue(width)]);
+                let style = Specified(if name == ~"table" {
+                    CSSBorderStyleOutset
+                } else {
+                    CSSBorderStyleSolid
+                });
+                hints.push_all([BorderTopStyleValue(style), BorderRightStyleValue(style),
+                                BorderBottomStyleValue(style), BorderLeftStyleValue(style)]);
+            }
+        }
+        _ => ()
+    }
+    match name {
+        ~"img" | ~"object" => {
+            for attr("hspace").chain(legacy_pixels).each |&space| {
+                let space = Specified(CSSMarginLength(space));
+                hints.push_all([MarginLeftValue(space), MarginRightValue(space)]);
+            }
+            for attr("vspace").chain(legacy_pixels).each |&space| {
+                let space = Specified(CSSMarginLength(space));
+                hints.push_all([MarginTopValue(space), MarginBottomValue(space)]);
+            }
+        }
+        ~"td" | ~"th" => {
+            for attr("cellpadding").chain(legacy_pixels).each |&padding| {
+                let padding = Specified(CSSPaddingLength(padding));
+                hints.push_all([PaddingTopValue(padding), PaddingRightValue(padding),
+                                PaddingBottomValue(padding), PaddingLeftValue(padding)]);
+            }
+        }
+        _ => ()
+    }
+    if name == ~"font" {
+        for attr("color").chain(legacy_color).each |&color| {
+            hints.push(ColorValue(Specified(CSSColorColor(color))));
+        }
+        for attr("face").chain(legacy_font_family).each |families| {
+            hints.push(FontFamilyValue(Specified(copy *families)));
+        }
+        for attr("size").chain(legacy_font_size).each |&size| {
+            hints.push(FontSizeValue(Specified(CSSFontSizeAbsoluteSize(size))));
+        }
+    }
+    hints
+}
+
+/** A color, which HTML allows without the `#` of hex colors */
+fn legacy_color(value: ~str) -> Option<Color> {
+    let value = value.trim();
+    if value.starts_with("#") {
+        parse_hex_color(value.slice(1, value.len()))
+    } else if (value.len() == 3 || value.len() == 6) && value.all(|c| char::is_digit_radix(c, 16)) {
+        parse_hex_color(value)
+    } else if !value.is_empty() && value.all(char::is_alphanumeric) {
+        parse_color_keyword(value)
+    } else {
+        None
+    }
+}
+
+/** A non-negative number of pixels, or a percentage, and whether it is a percentage */
+fn legacy_length(value: ~str) -> Option<(float, bool)> {
+    let value = value.trim();
+    if value.ends_with("%") {
+        legacy_number(value.slice(0, value.len() - 1)).map(|&n| (n, true))
+    } else {
+        legacy_number(value).map(|&n| (n, false))
+    }
+}
+
+/** A non-negative number of pixels */
+fn legacy_pixels(value: ~str) -> Option<Length> {
+    legacy_number(value.trim()).map(|&n| Px(n))
+}
+
+/** The leading digits of `value`, ignoring anything after them */
+fn legacy_number(value: &str) -> Option<float> {
+    let mut end = 0;
+    while end < value.len() && (char::is_digit(value.char_at(end)) || value.char_at(end) == '.') {
+        end += 1;
+    }
+    if end == 0 { None } else { float::from_str(value.slice(0, end)) }
+}
+
+/** `<font size>`: 1 to 7, or relative to the default of 3 */
+fn legacy_font_size(value: ~str) -> Option<AbsoluteSize> {
+    let value = value.trim();
+    let (relative, digits) = if value.starts_with("+") {
+        (1, value.slice(1, value.len()))
+    } else if value.starts_with("-") {
+        (-1, value.slice(1, value.len()))
+    } else {
+        (0, value)
+    };
+    let size = match int::from_str(digits) {
+        Some(n) if relative == 0 => n,
+        Some(n) => 3 + relative * n,
+        None => return None
+    };
+    Some(match int::max(1, int::min(size, 7)) {
+        1 => XSmall,
+        2 => Small,
+        3 => Medium,
+        4 => Large,
+        5 => XLarge,
+        _ => XXLarge
+    })
+}
+
+/** `<font face>`: a comma-separated list of family names, taken as they are */
+fn legacy_font_family(value: ~str) -> Option<~[CSSFontFamily]> {
+    let families = do str::split_char(value, ',').filter_mapped |family| {
+        let family = family.trim();
+        if family.is_empty() { None } else { Some(CSSFontFamilyFamilyName(family.to_owned())) }
+    };
+    if families.is_empty() { None } else { Some(families) }
+}
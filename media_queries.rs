@@ -0,0 +1,420 @@
+/*!
+Media queries, per Media Queries Level 3, and the device they are evaluated
+against.
+
+libcss only knows media types, so a `SelectCtx` evaluates the feature
+expressions of its sheets itself and hands libcss the rules that apply to
+its `Device`.
+*/
+
+use types::{MediaType, MediaAll};
+use units::{Length, Em, Ex, Px, Pt};
+use rules::{Rule, RuleMedia};
+use properties::parse_length;
+use syntax::{Token, Ident, Number, Dimension, Colon, LeftParen, RightParen, Whitespace, Delim};
+
+/// The font size `em` is relative to in a media query, that of `medium`
+const INITIAL_FONT_SIZE_PX: float = 16.0;
+/// The x-height of that font as a fraction of its size, for `ex`
+const INITIAL_X_HEIGHT_RATIO: float = 0.5;
+
+/**
+The output device styles are selected for. Sizes are in CSS pixels. The
+width and height of the viewport are also used for `device-width` and
+`device-height`.
+*/
+#[deriving_eq]
+pub struct Device {
+    width: float,
+    height: float,
+    /// Device pixels per CSS pixel
+    pixel_ratio: float,
+    /// Bits per color component, or 0 for a monochrome device
+    color: uint,
+    /// Bits per pixel of a monochrome device, or 0 for a color one
+    monochrome: uint,
+    scan: Scan
+}
+
+pub impl Device {
+    /** A progressive, 8 bit color screen with a `width` by `height` viewport */
+    static fn new(width: float, height: float) -> Device {
+        Device {
+            width: width,
+            height: height,
+            pixel_ratio: 1.0,
+            color: 8,
+            monochrome: 0,
+            scan: ScanProgressive
+        }
+    }
+
+    /** Portrait unless the viewport is wider than it is tall */
+    fn orientation(&self) -> Orientation {
+        if self.width > self.height {
+            OrientationLandscape
+        } else {
+            OrientationPortrait
+        }
+    }
+}
+
+#[deriving_eq]
+pub enum Orientation {
+    OrientationPortrait,
+    OrientationLandscape
+}
+
+#[deriving_eq]
+pub enum Scan {
+    ScanProgressive,
+    ScanInterlace
+}
+
+/**
+A single media query, e.g. `not print and (min-width: 600px)`. It matches
+when the media type does and every expression holds, or for a negated
+query when that isn't so.
+*/
+#[deriving_eq]
+pub struct MediaQuery {
+    negated: bool,
+    media_type: MediaType,
+    expressions: ~[MediaFeature]
+}
+
+pub impl MediaQuery {
+    /** A query for just a media type */
+    static fn new(media_type: MediaType) -> MediaQuery {
+        MediaQuery {
+            negated: false,
+            media_type: media_type,
+            expressions: ~[]
+        }
+    }
+
+    /**
+    The libcss media types this query matches on `device`. Expressions
+    don't depend on the media type, so they are evaluated here.
+    */
+    fn to_net(&self, device: &Device) -> u64 {
+        let matches = self.expressions.all(|expression| expression.matches(device));
+        let bits = if matches { self.media_type.to_net() } else { 0 };
+        if self.negated {
+            MediaAll.to_net() & !bits
+        } else {
+            bits
+        }
+    }
+}
+
+/** The comparison in a feature expression: `min-`, `max-`, exact or none */
+#[deriving_eq]
+pub enum Range<T> {
+    RangeMin(T),
+    RangeMax(T),
+    RangeExact(T),
+    /// The feature alone, e.g. `(color)`, true when it isn't zero
+    RangeNonZero
+}
+
+/** A media feature expression, such as `(max-width: 600px)` */
+#[deriving_eq]
+pub enum MediaFeature {
+    FeatureWidth(Range<Length>),
+    FeatureHeight(Range<Length>),
+    FeatureDeviceWidth(Range<Length>),
+    FeatureDeviceHeight(Range<Length>),
+    FeatureOrientation(Orientation),
+    /// Width and height, e.g. `16/9`
+    FeatureAspectRatio(Range<(uint, uint)>),
+    FeatureDeviceAspectRatio(Range<(uint, uint)>),
+    FeatureColor(Range<uint>),
+    FeatureColorIndex(Range<uint>),
+    FeatureMonochrome(Range<uint>),
+    /// In dots per CSS pixel
+    FeatureResolution(Range<float>),
+    FeatureScan(Scan),
+    FeatureGrid(bool)
+}
+
+pub impl MediaFeature {
+    fn matches(&self, device: &Device) -> bool {
+        match *self {
+            FeatureWidth(ref range) | FeatureDeviceWidth(ref range) => {
+                compare(&range.map(length_to_px), device.width)
+            }
+            FeatureHeight(ref range) | FeatureDeviceHeight(ref range) => {
+                compare(&range.map(length_to_px), device.height)
+            }
+            FeatureOrientation(orientation) => orientation == device.orientation(),
+            FeatureAspectRatio(ref range) | FeatureDeviceAspectRatio(ref range) => {
+                let ratio = |&(w, h): &(uint, uint)| (w as float) / (h as float);
+                compare(&range.map(ratio), device.width / device.height)
+            }
+            FeatureColor(ref range) => compare(&range.map(|c| *c as float), device.color as float),
+            // Devices with a color lookup table aren't described
+            FeatureColorIndex(ref range) => compare(&range.map(|c| *c as float), 0.0),
+            FeatureMonochrome(ref range) => {
+                compare(&range.map(|m| *m as float), device.monochrome as float)
+            }
+            FeatureResolution(ref range) => compare(range, device.pixel_ratio),
+            FeatureScan(scan) => scan == device.scan,
+            // Only bitmap devices are described
+            FeatureGrid(grid) => !grid
+        }
+    }
+}
+
+pub impl<T: Copy> Range<T> {
+    fn map<U>(&self, f: &fn(&T) -> U) -> Range<U> {
+        match *self {
+            RangeMin(ref value) => RangeMin(f(value)),
+            RangeMax(ref value) => RangeMax(f(value)),
+            RangeExact(ref value) => RangeExact(f(value)),
+            RangeNonZero => RangeNonZero
+        }
+    }
+}
+
+/** Whether `actual` is in `range` */
+fn compare(range: &Range<float>, actual: float) -> bool {
+    match *range {
+        RangeMin(value) => actual >= value,
+        RangeMax(value) => actual <= value,
+        RangeExact(value) => actual == value,
+        RangeNonZero => actual != 0.0
+    }
+}
+
+fn length_to_px(length: &Length) -> float {
+    match *length {
+        Px(value) => value,
+        Em(value) => value * INITIAL_FONT_SIZE_PX,
+        Ex(value) => value * INITIAL_FONT_SIZE_PX * INITIAL_X_HEIGHT_RATIO,
+        Pt(value) => value * 96.0 / 72.0
+    }
+}
+
+/**
+A single query from a media query list. Queries that can't be parsed, or
+that name an unknown media type or feature, give `None`; they never match.
+*/
+pub fn parse_media_query(tokens: &[Token]) -> Option<MediaQuery> {
+    let tokens = tokens.filtered(|token| *token != Whitespace);
+    let mut i = 0;
+    let mut negated = false;
+    let mut media_type = MediaAll;
+
+    match token_at(tokens, i) {
+        Some(Ident(ref name)) => {
+            let mut name = name.to_lower();
+            if name == ~"not" || name == ~"only" {
+                negated = name == ~"not";
+                i += 1;
+                name = match token_at(tokens, i) {
+                    Some(Ident(ref name)) => name.to_lower(),
+                    _ => return None
+                };
+            }
+            media_type = match MediaType::from_name(name) {
+                Some(media_type) => media_type,
+                None => return None
+            };
+            i += 1;
+            if i < tokens.len() {
+                if !is_and(&tokens[i]) || i + 1 == tokens.len() {
+                    return None;
+                }
+                i += 1;
+            }
+        }
+        Some(LeftParen) => (),
+        _ => return None
+    }
+
+    let mut expressions = ~[];
+    while i < tokens.len() {
+        let start = i;
+        while i < tokens.len() && tokens[i] != RightParen {
+            i += 1;
+        }
+        if tokens[start] != LeftParen || i == tokens.len() {
+            return None;
+        }
+        match parse_expression(tokens.slice(start + 1, i)) {
+            Some(expression) => expressions.push(expression),
+            None => return None
+        }
+        i += 1;
+        if i < tokens.len() {
+            if !is_and(&tokens[i]) || i + 1 == tokens.len() {
+                return None;
+            }
+            i += 1;
+        }
+    }
+
+    Some(MediaQuery {
+        negated: negated,
+        media_type: media_type,
+        expressions: expressions
+    })
+}
+
+fn token_at(tokens: &[Token], i: uint) -> Option<Token> {
+    if i < tokens.len() { Some(copy tokens[i]) } else { None }
+}
+
+fn is_and(token: &Token) -> bool {
+    match *token {
+        Ident(ref name) => name.to_lower() == ~"and",
+        _ => false
+    }
+}
+
+/** The inside of a parenthesized expression, e.g. `max-width: 600px` */
+fn parse_expression(tokens: &[Token]) -> Option<MediaFeature> {
+    let (name, value) = match tokens {
+        [Ident(ref name)] => (name.to_lower(), ~[]),
+        [Ident(ref name), Colon, ..value] if !value.is_empty() => {
+            (name.to_lower(), value.to_owned())
+        }
+        _ => return None
+    };
+    let (prefix, feature) = if name.starts_with("min-") || name.starts_with("max-") {
+        (name.slice(0, 4).to_owned(), name.slice(4, name.len()).to_owned())
+    } else {
+        (~"", copy name)
+    };
+    // The features that take min- and max- prefixes
+    match feature {
+        ~"width" => range(prefix, value, parse_length).map(|r| FeatureWidth(copy *r)),
+        ~"height" => range(prefix, value, parse_length).map(|r| FeatureHeight(copy *r)),
+        ~"device-width" => range(prefix, value, parse_length).map(|r| FeatureDeviceWidth(copy *r)),
+        ~"device-height" => range(prefix, value, parse_length).map(|r| FeatureDeviceHeight(copy *r)),
+        ~"aspect-ratio" => range(prefix, value, parse_ratio).map(|r| FeatureAspectRatio(copy *r)),
+        ~"device-aspect-ratio" => {
+            range(prefix, value, parse_ratio).map(|r| FeatureDeviceAspectRatio(copy *r))
+        }
+        ~"color" => range(prefix, value, parse_integer).map(|r| FeatureColor(copy *r)),
+        ~"color-index" => range(prefix, value, parse_integer).map(|r| FeatureColorIndex(copy *r)),
+        ~"monochrome" => range(prefix, value, parse_integer).map(|r| FeatureMonochrome(copy *r)),
+        ~"resolution" => range(prefix, value, parse_resolution).map(|r| FeatureResolution(*r)),
+        _ if !prefix.is_empty() => None,
+        ~"orientation" => {
+            match value {
+                [Ident(ref keyword)] if keyword.to_lower() == ~"portrait" => {
+                    Some(FeatureOrientation(OrientationPortrait))
+                }
+                [Ident(ref keyword)] if keyword.to_lower() == ~"landscape" => {
+                    Some(FeatureOrientation(OrientationLandscape))
+                }
+                _ => None
+            }
+        }
+        ~"scan" => {
+            match value {
+                [Ident(ref keyword)] if keyword.to_lower() == ~"progressive" => {
+                    Some(FeatureScan(ScanProgressive))
+                }
+                [Ident(ref keyword)] if keyword.to_lower() == ~"interlace" => {
+                    Some(FeatureScan(ScanInterlace))
+                }
+                _ => None
+            }
+        }
+        ~"grid" => {
+            match value {
+                [] => Some(FeatureGrid(true)),
+                [Number(0.0)] => Some(FeatureGrid(false)),
+                [Number(1.0)] => Some(FeatureGrid(true)),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+/** A `min-`, `max-` or exact value, or just the feature's name */
+fn range<T: Copy>(prefix: &str, value: &[Token], parse: &fn(&[Token]) -> Option<T>)
+                 -> Option<Range<T>> {
+    if value.is_empty() {
+        return if prefix.is_empty() { Some(RangeNonZero) } else { None };
+    }
+    do parse(value).map |value| {
+        if prefix == "min-" {
+            RangeMin(copy *value)
+        } else if prefix == "max-" {
+            RangeMax(copy *value)
+        } else {
+            RangeExact(copy *value)
+        }
+    }
+}
+
+fn parse_integer(value: &[Token]) -> Option<uint> {
+    match value {
+        [Number(n)] if n >= 0.0 && n == (n as uint) as float => Some(n as uint),
+        _ => None
+    }
+}
+
+fn parse_ratio(value: &[Token]) -> Option<(uint, uint)> {
+    match value {
+        [Number(_), Delim('/'), Number(_)] => {
+            match (parse_integer(value.slice(0, 1)), parse_integer(value.slice(2, 3))) {
+                (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+/** A resolution in dots per CSS pixel */
+fn parse_resolution(value: &[Token]) -> Option<float> {
+    match value {
+        [Dimension(n, ref unit)] if n > 0.0 => {
+            match unit.to_lower() {
+                ~"dppx" => Some(n),
+                ~"dpi" => Some(n / 96.0),
+                ~"dpcm" => Some(n * 2.54 / 96.0),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+/** Whether any `@media` rule in `rules` has a feature expression */
+pub fn rules_depend_on_device(rules: &[Rule]) -> bool {
+    do rules.any |rule| {
+        match *rule {
+            RuleMedia(ref media_rule) => {
+                media_rule.media.depends_on_device() || rules_depend_on_device(media_rule.rules)
+            }
+            _ => false
+        }
+    }
+}
+
+/**
+The indices of those of `rules` that apply differently on `new_device` than
+on `old_device`
+*/
+pub fn rules_changed_by_device(rules: &[Rule], old_device: &Device,
+                               new_device: &Device) -> ~[uint] {
+    let mut changed = ~[];
+    for rules.eachi |i, rule| {
+        match *rule {
+            RuleMedia(ref media_rule) => {
+                if media_rule.media.to_net(old_device) != media_rule.media.to_net(new_device) {
+                    changed.push(i);
+                }
+            }
+            _ => ()
+        }
+    }
+    changed
+}
@@ -0,0 +1,111 @@
+/*!
+Page boxes for paged media, CSS 2.1 section 13.
+
+libcss doesn't cascade `@page` rules, so a `SelectCtx` keeps the page rules
+of its print sheets and cascades them here.
+*/
+
+use types::{StylesheetOrigin, OriginUA, OriginUser, OriginAuthor};
+use rules::{PageRule, PageFirst, PageLeft, PageRight};
+use properties::{PropertyDeclaration, MarginTopValue, MarginRightValue, MarginBottomValue};
+use properties::{MarginLeftValue, SizeValue};
+use values::*;
+use units::Px;
+
+/** Which side of a spread a page falls on */
+#[deriving_eq]
+pub enum PageSide {
+    PageSideLeft,
+    PageSideRight
+}
+
+/** The computed style of a page box */
+#[deriving_eq]
+pub struct PageStyle {
+    size: CSSPageSize,
+    margin_top: CSSMargin,
+    margin_right: CSSMargin,
+    margin_bottom: CSSMargin,
+    margin_left: CSSMargin
+}
+
+pub impl PageStyle {
+    /** A page with no `@page` rules applied */
+    static fn initial() -> PageStyle {
+        PageStyle {
+            size: CSSPageSizeAuto,
+            margin_top: CSSMarginLength(Px(0.0)),
+            margin_right: CSSMarginLength(Px(0.0)),
+            margin_bottom: CSSMarginLength(Px(0.0)),
+            margin_left: CSSMarginLength(Px(0.0))
+        }
+    }
+}
+
+/**
+Cascade the `@page` rules that apply to the page at `page_index`, counting
+from 0, which falls on `side`. `rules` are in cascade order, as the sheets
+were appended. A page box has no parent, so `inherit` gives the initial
+value.
+*/
+pub fn cascade_page_rules(rules: &[(StylesheetOrigin, PageRule)], page_index: uint,
+                          side: PageSide) -> PageStyle {
+    // Each applicable declaration with its place in the cascade
+    let mut declarations = ~[];
+    for rules.each |&(origin, ref rule)| {
+        let specificity = match rule.selector {
+            None => 0,
+            Some(PageFirst) if page_index == 0 => PageFirst.specificity(),
+            Some(PageLeft) if side == PageSideLeft => PageLeft.specificity(),
+            Some(PageRight) if side == PageSideRight => PageRight.specificity(),
+            Some(_) => loop
+        };
+        for rule.declarations.each |declaration| {
+            let weight = cascade_level(origin, declaration.important) * 10 + specificity;
+            declarations.push((weight, copy *declaration));
+        }
+    }
+    // A stable sort, so that later rules of the same weight win
+    let declarations = std::sort::merge_sort(declarations, |&(a, _), &(b, _)| a <= b);
+
+    let mut style = PageStyle::initial();
+    let initial = PageStyle::initial();
+    for declarations.each |&(_, ref declaration)| {
+        apply_declaration(&mut style, &initial, declaration);
+    }
+    style
+}
+
+/** CSS 2.1 section 6.4.1, with `!important` user agent declarations on top */
+fn cascade_level(origin: StylesheetOrigin, important: bool) -> uint {
+    match (origin, important) {
+        (OriginUA, false) => 0,
+        (OriginUser, false) => 1,
+        (OriginAuthor, false) => 2,
+        (OriginAuthor, true) => 3,
+        (OriginUser, true) => 4,
+        (OriginUA, true) => 5
+    }
+}
+
+fn apply_declaration(style: &mut PageStyle, initial: &PageStyle,
+                     declaration: &PropertyDeclaration) {
+    match declaration.value {
+        MarginTopValue(ref value) => style.margin_top = or_initial(value, initial.margin_top),
+        MarginRightValue(ref value) => style.margin_right = or_initial(value, initial.margin_right),
+        MarginBottomValue(ref value) => {
+            style.margin_bottom = or_initial(value, initial.margin_bottom)
+        }
+        MarginLeftValue(ref value) => style.margin_left = or_initial(value, initial.margin_left),
+        SizeValue(ref value) => style.size = or_initial(value, initial.size),
+        // Other properties apply to the page's content, which isn't styled here
+        _ => ()
+    }
+}
+
+fn or_initial<T: Copy>(value: &CSSValue<T>, initial: T) -> T {
+    match *value {
+        Specified(ref value) => copy *value,
+        Inherit => initial
+    }
+}
@@ -2,52 +2,548 @@
 Constructs a list of css style rules from a token stream
 */
 
-// TODO: fail according to the css spec instead of failing when things
-// are not as expected
-
-use util::DataStream;
+use util::{DataStream, bytes_stream};
 use core::cell::Cell;
 use netsurfcss::stylesheet::{CssStylesheet, CssStylesheetParams, CssStylesheetParamsVersion1, css_stylesheet_create};
-use netsurfcss::types::CssLevel21;
 use netsurfcss::CssResult;
+use netsurfcss::errors::CssInvalid;
 use wapcaplet::LwcString;
+use lwcstr_from_rust_str = wapcaplet::from_rust_string;
 use std::net::url::Url;
-use netsurfcss::stylesheet::CssUrlResolutionFn;
+use url_from_str = std::net::url::from_str;
+use netsurfcss::stylesheet::{CssUrlResolutionFn, CssImportNotificationFn, CssColorResolutionFn};
+use netsurfcss::types::CssColor;
+use stylesheet::{Stylesheet, StylesheetParams, ColorResolver};
+use types::{MediaList, LanguageLevel, EncodingFromProtocol};
+use charset::{CHARSET_SNIFF_LENGTH, determine_encoding, decode};
+use syntax::{Span, RuleSpan, ParseDiagnostic, SeverityWarning, UnsupportedEncoding};
+use syntax::{parse_rules, parse_inline_declarations};
+use rules::{Rule, RuleStyle, RuleMedia, StyleRule, rule_from_raw};
+use properties::parse_declaration;
+use media_queries::Device;
+use serialize::ToCss;
+
+/// How deeply `@import`s may nest before we stop following them
+const MAX_IMPORT_DEPTH: uint = 16;
 
 // This takes a DataStreamFactory instead of a DataStream because
 // servo's DataStream contains a comm::Port, which is not sendable,
 // so DataStream is an @fn which can't be sent to the lexer task.
 // So the DataStreamFactory gives the caller an opportunity to create
 // the data stream from inside the lexer task.
-pub fn parse_stylesheet(url: Url, input: DataStream) -> CssStylesheet {
+pub fn parse_stylesheet(url: Url, input: DataStream, params: &StylesheetParams) -> Stylesheet {
+    parse_imported_stylesheet(url, input, false, false, params, ~[])
+}
+
+/**
+Parse a stylesheet that is already text. It is read as UTF-8, the
+encoding given as the protocol's, whatever any BOM or `@charset` rule says.
+*/
+pub fn parse_stylesheet_str(url: Url, css: &str) -> Stylesheet {
+    let params = StylesheetParams::new().protocol_encoding("UTF-8");
+    parse_imported_stylesheet(url, bytes_stream(str::to_bytes(css)), false, true, &params, ~[])
+}
+
+/**
+Parse the contents of a `style` attribute, a bare list of declarations.
+`url` is the base used to resolve any urls in the declarations. The text
+is always UTF-8.
+*/
+pub fn parse_inline_style(url: Url, input: DataStream) -> Stylesheet {
+    let params = StylesheetParams::new().protocol_encoding("UTF-8");
+    parse_imported_stylesheet(url, input, true, true, &params, ~[])
+}
+
+/**
+Parse a single sheet and, recursively, everything it imports. If
+`dictated`, the sheet is in the protocol encoding of `params` whatever it
+says itself. `ancestors` holds the URLs of the sheets importing this one,
+for cycle detection.
+*/
+fn parse_imported_stylesheet(url: Url, input: DataStream, inline_style: bool, dictated: bool,
+                             params: &StylesheetParams,
+                             ancestors: ~[~str]) -> Stylesheet {
+    // Buffer enough of the sheet to see any BOM or @charset rule, since
+    // libcss needs to know the encoding before it gets any data
+    let mut prefix = ~[];
+    let mut complete = false;
+    while !complete && prefix.len() < CHARSET_SNIFF_LENGTH {
+        match input() {
+            Some(data) => vec::push_all(&mut prefix, data),
+            None => complete = true
+        }
+    }
+    let (encoding, encoding_source) = match params.protocol_encoding {
+        Some(ref protocol) if dictated => (copy *protocol, EncodingFromProtocol),
+        _ => determine_encoding(prefix, &params.protocol_encoding, &params.environment_encoding)
+    };
+
+    // libcss hands us the already-resolved url of each @import as it is parsed
+    let pending_imports: @mut ~[(~str, MediaList)] = @mut ~[];
+    let import: CssImportNotificationFn = |_parent, import_url, import_media| {
+        pending_imports.push((import_url.to_str(), MediaList::from_net(*import_media)));
+        Ok(())
+    };
+    let mut sheet = create_net_stylesheet(&url, encoding, params.level, params.allow_quirks,
+                                          &params.title, inline_style, Some(import),
+                                          params.color_resolver);
+
+    // libcss sees the data as it arrives. It is kept as well, since libcss
+    // has to be given it again to copy the sheet or build it for a device
+    let mut source = copy prefix;
+    sheet.append_data(prefix);
+    while !complete {
+        match input() {
+            Some(data) => {
+                vec::push_all(&mut source, data);
+                sheet.append_data(data);
+            }
+            None => complete = true
+        }
+    }
+    sheet.data_done();
+
+    let text = decode(source, encoding);
+    let (rules, spans, diagnostics) = match text {
+        Some(ref text) => parse_decoded(*text, inline_style, &url),
+        None => {
+            let diagnostic = ParseDiagnostic {
+                severity: SeverityWarning,
+                line: 1,
+                column: 1,
+                token: copy encoding,
+                reason: UnsupportedEncoding
+            };
+            (~[], ~[], ~[diagnostic])
+        }
+    };
+    // The spans of the rules are in the decoded text
+    let (source, source_encoding) = match text {
+        Some(text) => (str::to_bytes(text), ~"UTF-8"),
+        None => (source, copy encoding)
+    };
+
+    let mut imports = ~[];
+    for params.loader.each |&loader| {
+        let mut ancestors = copy ancestors;
+        ancestors.push(url.to_str());
+        // Imported sheets fall back to the encoding of the sheet importing them
+        for pending_imports.each |import| {
+            let (import_url, import_media) = copy *import;
+            let import_params = StylesheetParams {
+                level: params.level,
+                allow_quirks: params.allow_quirks,
+                title: None,
+                media: import_media,
+                protocol_encoding: None,
+                environment_encoding: Some(copy encoding),
+                loader: Some(loader),
+                color_resolver: params.color_resolver
+            };
+            match load_import(import_url, &import_params, ancestors) {
+                Some(imported) => imports.push(imported),
+                None => ()
+            }
+        }
+    }
+
+    Stylesheet {
+        inner: sheet,
+        url: copy url,
+        level: params.level,
+        allow_quirks: params.allow_quirks,
+        inline_style: inline_style,
+        imports: imports,
+        media: copy params.media,
+        title: copy params.title,
+        encoding: encoding,
+        encoding_source: encoding_source,
+        source: source,
+        source_encoding: source_encoding,
+        decoded: text.is_some(),
+        rules: rules,
+        spans: spans,
+        diagnostics: diagnostics,
+        color_resolver: params.color_resolver
+    }
+}
+
+/**
+Parse the decoded `text` of a sheet ourselves, for `Stylesheet::rules` and
+`Stylesheet::diagnostics`. Returns the rules along with where each is in
+`text`.
+*/
+fn parse_decoded(text: &str, inline_style: bool,
+                 url: &Url) -> (~[Rule], ~[RuleSpan], ~[ParseDiagnostic]) {
+    if inline_style {
+        let (raw_declarations, diagnostics) = parse_inline_declarations(text);
+        let mut declarations = ~[];
+        for raw_declarations.each |declaration| {
+            declarations.push_all(parse_declaration(declaration, url));
+        }
+        // A style attribute is a single rule with no selectors
+        let rule = RuleStyle(StyleRule {
+            selectors: ~[],
+            declarations: declarations
+        });
+        let span = RuleSpan {
+            rule: Span { start: 0, end: text.len() },
+            block: None
+        };
+        (~[rule], ~[span], diagnostics)
+    } else {
+        let (raw_rules, diagnostics) = parse_rules(text);
+        let mut rules = ~[];
+        let mut spans = ~[];
+        for raw_rules.each |raw| {
+            match rule_from_raw(raw, url) {
+                Some(rule) => {
+                    rules.push(rule);
+                    spans.push(raw.span);
+                }
+                None => ()
+            }
+        }
+        (rules, spans, diagnostics)
+    }
+}
+
+fn create_net_stylesheet(url: &Url, encoding: &str, level: LanguageLevel, allow_quirks: bool,
+                         title: &Option<~str>, inline_style: bool,
+                         import: Option<CssImportNotificationFn>,
+                         color_resolver: Option<@ColorResolver>) -> CssStylesheet {
     let resolve: CssUrlResolutionFn = resolve_url;
-    let params: CssStylesheetParams = CssStylesheetParams {
+    let color = do color_resolver.map |&resolver| {
+        let color: CssColorResolutionFn = |name| resolve_system_color(resolver, name);
+        color
+    };
+    let net_params: CssStylesheetParams = CssStylesheetParams {
         params_version: CssStylesheetParamsVersion1,
-        level: CssLevel21,
-        charset: ~"UTF-8",
+        level: level.to_net(),
+        charset: encoding.to_owned(),
         url: url.to_str(),
-        title: ~"FIXME-css-title",
-        allow_quirks: false,
-        inline_style: false,
+        title: title.get_or_default(~""),
+        allow_quirks: allow_quirks,
+        inline_style: inline_style,
         resolve: Some(resolve),
-        import: None,
-        color: None,
+        import: import,
+        color: color,
         font: None,
     };
-    let mut sheet = css_stylesheet_create(&params);
+    css_stylesheet_create(&net_params)
+}
 
-    loop {
-        match input() {
-            Some(data) => {
-                sheet.append_data(data);
+/**
+Build a new libcss sheet from the source of `sheet`, for when its rules have
+changed or a second copy is needed. libcss has no way to edit a sheet it has
+parsed, so it is given the text again. Imports are not followed; the sheets
+they name are still in `sheet.imports`.
+*/
+pub fn rebuild_net_stylesheet(sheet: &Stylesheet) -> CssStylesheet {
+    net_stylesheet_from_source(sheet, sheet.source)
+}
+
+/**
+Build the libcss sheet for `sheet` as it applies on `device`, for sheets
+with media feature expressions libcss can't evaluate. `@media` rules with
+feature expressions that don't match `device` are left out, and the rest
+have their queries reduced to the media types they match, which is all
+libcss understands. Everything else is passed on as written.
+*/
+pub fn net_stylesheet_for_device(sheet: &Stylesheet, device: &Device) -> CssStylesheet {
+    let mut source = ~[];
+    let mut copied_to = 0;
+    for sheet.rules.eachi |i, rule| {
+        let media_rule = match *rule {
+            RuleMedia(ref media_rule) if media_rule.media.depends_on_device() => media_rule,
+            _ => loop
+        };
+        let span = sheet.spans[i];
+        source.push_all(sheet.source.slice(copied_to, span.rule.start));
+        copied_to = span.rule.end;
+
+        let media = media_rule.media.to_net(device);
+        match span.block {
+            Some(block) if media != 0 => {
+                let media = MediaList::from_net(media).to_css();
+                source.push_all(str::to_bytes(fmt!("@media %s {", media)));
+                source.push_all(sheet.source.slice(block.start, block.end));
+                source.push_all(str::to_bytes("}"));
             }
-            None => break
+            _ => ()
         }
     }
-    sheet.data_done();
-    return sheet;
+    source.push_all(sheet.source.slice(copied_to, sheet.source.len()));
+    net_stylesheet_from_source(sheet, source)
+}
+
+fn net_stylesheet_from_source(sheet: &Stylesheet, source: &[u8]) -> CssStylesheet {
+    let mut inner = create_net_stylesheet(&sheet.url, sheet.source_encoding, sheet.level,
+                                          sheet.allow_quirks, &sheet.title, sheet.inline_style,
+                                          None, sheet.color_resolver);
+    inner.append_data(source.to_owned());
+    inner.data_done();
+    inner
 }
 
-fn resolve_url(_base: &str, _rel: &LwcString) -> CssResult<LwcString> {
-    fail!(~"resolving url");
+fn load_import(url_str: &str, params: &StylesheetParams,
+               ancestors: &[~str]) -> Option<Stylesheet> {
+    if ancestors.contains(&url_str.to_owned()) {
+        warn!("ignoring circular @import of %?", url_str);
+        return None;
+    }
+    if ancestors.len() >= MAX_IMPORT_DEPTH {
+        warn!("ignoring @import of %?, imports nested too deeply", url_str);
+        return None;
+    }
+    let url = match url_from_str(url_str) {
+        Ok(url) => url,
+        Err(_) => {
+            warn!("ignoring @import of unparseable url %?", url_str);
+            return None;
+        }
+    };
+    let loader = params.loader.get();
+    match loader.load(&url, &params.media) {
+        Some(input) => Some(parse_imported_stylesheet(url, input, false, false, params,
+                                                      ancestors.to_owned())),
+        None => {
+            warn!("unable to load @import %?", url_str);
+            None
+        }
+    }
+}
+
+fn resolve_system_color(resolver: @ColorResolver, name: &LwcString) -> CssResult<CssColor> {
+    match resolver.resolve_system_color(name.to_str_slice().to_lower()) {
+        Some(color) => Ok(CssColor {
+            r: color.red,
+            g: color.green,
+            b: color.blue,
+            a: (color.alpha * 255.0 + 0.5) as u8
+        }),
+        None => {
+            warn!("unknown system color %?", name.to_str_slice());
+            Err(CssInvalid)
+        }
+    }
+}
+
+fn resolve_url(base: &str, rel: &LwcString) -> CssResult<LwcString> {
+    match resolve_relative(base, rel.to_str_slice()) {
+        Some(url) => Ok(lwcstr_from_rust_str(url)),
+        None => {
+            warn!("unable to resolve url %? against %?", rel.to_str_slice(), base);
+            Err(CssInvalid)
+        }
+    }
+}
+
+/** The five components of a URI reference, RFC 3986 section 3 */
+struct UrlParts {
+    scheme: Option<~str>,
+    authority: Option<~str>,
+    path: ~str,
+    query: Option<~str>,
+    fragment: Option<~str>
+}
+
+/**
+Resolve the reference `rel` against the absolute URL `base` following
+RFC 3986 section 5.2. Returns `None` if either input can't be parsed or
+`base` is not absolute.
+*/
+pub fn resolve_relative(base: &str, rel: &str) -> Option<~str> {
+    let base = match split_url(base) {
+        Some(base) => base,
+        None => return None
+    };
+    if base.scheme.is_none() {
+        return None;
+    }
+    let rel = match split_url(rel.trim()) {
+        Some(rel) => rel,
+        None => return None
+    };
+
+    // RFC 3986 5.2.2 - Transform References
+    let target = if rel.scheme.is_some() {
+        UrlParts {
+            path: remove_dot_segments(rel.path),
+            .. rel
+        }
+    } else if rel.authority.is_some() {
+        UrlParts {
+            scheme: base.scheme,
+            path: remove_dot_segments(rel.path),
+            .. rel
+        }
+    } else if rel.path.is_empty() {
+        UrlParts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: copy base.path,
+            query: if rel.query.is_some() { rel.query } else { base.query },
+            fragment: rel.fragment
+        }
+    } else if rel.path.starts_with("/") {
+        UrlParts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: remove_dot_segments(rel.path),
+            query: rel.query,
+            fragment: rel.fragment
+        }
+    } else {
+        let merged = merge_paths(&base, rel.path);
+        UrlParts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: remove_dot_segments(merged),
+            query: rel.query,
+            fragment: rel.fragment
+        }
+    };
+
+    Some(recompose(&target))
+}
+
+/** Split a URI reference into its components, RFC 3986 appendix B */
+fn split_url(s: &str) -> Option<UrlParts> {
+    for str::each_char(s) |c| {
+        if c <= ' ' || c == '\x7f' {
+            return None;
+        }
+    }
+
+    let (rest, fragment) = match str::find_char(s, '#') {
+        Some(i) => (s.slice(0, i), Some(s.slice(i + 1, s.len()).to_owned())),
+        None => (s, None)
+    };
+    let (rest, query) = match str::find_char(rest, '?') {
+        Some(i) => (rest.slice(0, i), Some(rest.slice(i + 1, rest.len()).to_owned())),
+        None => (rest, None)
+    };
+
+    // A colon before the first slash ends the scheme. Relative references
+    // can't have a colon in their first segment, so a bad scheme is an error.
+    let (rest, scheme) = match str::find_char(rest, ':') {
+        Some(i) if !str::contains_char(rest.slice(0, i), '/') => {
+            let scheme = rest.slice(0, i);
+            if !is_valid_scheme(scheme) {
+                return None;
+            }
+            (rest.slice(i + 1, rest.len()), Some(scheme.to_lower()))
+        }
+        _ => (rest, None)
+    };
+
+    let (path, authority) = if rest.starts_with("//") {
+        match str::find_char_from(rest, '/', 2) {
+            Some(i) => (rest.slice(i, rest.len()), Some(rest.slice(2, i).to_owned())),
+            None => ("", Some(rest.slice(2, rest.len()).to_owned()))
+        }
+    } else {
+        (rest, None)
+    };
+
+    Some(UrlParts {
+        scheme: scheme,
+        authority: authority,
+        path: path.to_owned(),
+        query: query,
+        fragment: fragment
+    })
+}
+
+fn is_valid_scheme(scheme: &str) -> bool {
+    if scheme.is_empty() || !char::is_alphabetic(scheme.char_at(0)) {
+        return false;
+    }
+    for str::each_char(scheme) |c| {
+        let ok = (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') ||
+            (c >= '0' && c <= '9') || c == '+' || c == '-' || c == '.';
+        if !ok {
+            return false;
+        }
+    }
+    return true;
+}
+
+/** RFC 3986 5.2.3 - Merge Paths */
+fn merge_paths(base: &UrlParts, rel_path: &str) -> ~str {
+    if base.authority.is_some() && base.path.is_empty() {
+        return ~"/" + rel_path;
+    }
+    match str::rfind_char(base.path, '/') {
+        Some(i) => base.path.slice(0, i + 1).to_owned() + rel_path,
+        None => rel_path.to_owned()
+    }
+}
+
+/** RFC 3986 5.2.4 - Remove Dot Segments */
+fn remove_dot_segments(path: &str) -> ~str {
+    let mut input = path.to_owned();
+    let mut output = ~"";
+
+    while !input.is_empty() {
+        if input.starts_with("../") {
+            input = input.slice(3, input.len()).to_owned();
+        } else if input.starts_with("./") {
+            input = input.slice(2, input.len()).to_owned();
+        } else if input.starts_with("/./") {
+            input = input.slice(2, input.len()).to_owned();
+        } else if input == ~"/." {
+            input = ~"/";
+        } else if input.starts_with("/../") {
+            input = input.slice(3, input.len()).to_owned();
+            pop_last_segment(&mut output);
+        } else if input == ~"/.." {
+            input = ~"/";
+            pop_last_segment(&mut output);
+        } else if input == ~"." || input == ~".." {
+            input = ~"";
+        } else {
+            let start = if input.starts_with("/") { 1 } else { 0 };
+            let end = match str::find_char_from(input, '/', start) {
+                Some(i) => i,
+                None => input.len()
+            };
+            str::push_str(&mut output, input.slice(0, end));
+            input = input.slice(end, input.len()).to_owned();
+        }
+    }
+
+    return output;
+}
+
+fn pop_last_segment(output: &mut ~str) {
+    let truncated = match str::rfind_char(*output, '/') {
+        Some(i) => output.slice(0, i).to_owned(),
+        None => ~""
+    };
+    *output = truncated;
+}
+
+/** RFC 3986 5.3 - Component Recomposition */
+fn recompose(parts: &UrlParts) -> ~str {
+    let mut result = ~"";
+    for parts.scheme.each |scheme| {
+        str::push_str(&mut result, *scheme);
+        str::push_char(&mut result, ':');
+    }
+    for parts.authority.each |authority| {
+        str::push_str(&mut result, "//");
+        str::push_str(&mut result, *authority);
+    }
+    str::push_str(&mut result, parts.path);
+    for parts.query.each |query| {
+        str::push_char(&mut result, '?');
+        str::push_str(&mut result, *query);
+    }
+    for parts.fragment.each |fragment| {
+        str::push_char(&mut result, '#');
+        str::push_str(&mut result, *fragment);
+    }
+    return result;
 }
@@ -0,0 +1,1570 @@
+/*!
+CSS properties, and their declarations as typed values
+*/
+
+use std::net::url::Url;
+use url_from_str = std::net::url::from_str;
+use color::{Color, rgb, rgba, hsla};
+use parse_color_keyword = color::parsing::parse_color;
+use values::*;
+use units::*;
+use parser::resolve_relative;
+use syntax::{RawDeclaration, SourceToken, Token, Ident, QuotedString, Hash, Number, Percentage};
+use syntax::{Dimension, Uri, UnicodeRange, Function, LeftParen, RightParen, Whitespace, Delim};
+use rules::{FontFaceRule, FontFaceSource, FontFaceSourceUrl, FontFaceSourceLocal};
+
+/**
+The properties of CSS 2.1 (appendix F), plus the CSS 3 properties libcss
+understands
+*/
+const KNOWN_PROPERTIES: &'static [&'static str] = &[
+    // CSS 2.1
+    "azimuth", "background", "background-attachment", "background-color",
+    "background-image", "background-position", "background-repeat", "border",
+    "border-bottom", "border-bottom-color", "border-bottom-style", "border-bottom-width",
+    "border-collapse", "border-color", "border-left", "border-left-color",
+    "border-left-style", "border-left-width", "border-right", "border-right-color",
+    "border-right-style", "border-right-width", "border-spacing", "border-style",
+    "border-top", "border-top-color", "border-top-style", "border-top-width",
+    "border-width", "bottom", "caption-side", "clear", "clip", "color", "content",
+    "counter-increment", "counter-reset", "cue", "cue-after", "cue-before", "cursor",
+    "direction", "display", "elevation", "empty-cells", "float", "font", "font-family",
+    "font-size", "font-style", "font-variant", "font-weight", "height", "left",
+    "letter-spacing", "line-height", "list-style", "list-style-image",
+    "list-style-position", "list-style-type", "margin", "margin-bottom", "margin-left",
+    "margin-right", "margin-top", "max-height", "max-width", "min-height", "min-width",
+    "orphans", "outline", "outline-color", "outline-style", "outline-width", "overflow",
+    "padding", "padding-bottom", "padding-left", "padding-right", "padding-top",
+    "page-break-after", "page-break-before", "page-break-inside", "pause", "pause-after",
+    "pause-before", "pitch", "pitch-range", "play-during", "position", "quotes",
+    "richness", "right", "speak", "speak-header", "speak-numeral", "speak-punctuation",
+    "speech-rate", "stress", "table-layout", "text-align", "text-decoration",
+    "text-indent", "text-transform", "top", "unicode-bidi", "vertical-align",
+    "visibility", "voice-family", "volume", "white-space", "widows", "width",
+    "word-spacing", "z-index",
+    // CSS 3
+    "opacity", "overflow-x", "overflow-y", "word-wrap", "writing-mode", "box-sizing",
+    "break-after", "break-before", "break-inside", "column-count", "column-fill",
+    "column-gap", "column-rule", "column-rule-color", "column-rule-style",
+    "column-rule-width", "column-span", "column-width", "columns"
+];
+
+/** Whether `name`, which must be lower case, names a supported property */
+pub fn is_known_property(name: &str) -> bool {
+    KNOWN_PROPERTIES.any(|property| str::eq_slice(*property, name))
+}
+
+/** As `is_known_property`, for the declarations of an `@page` rule */
+pub fn is_known_page_property(name: &str) -> bool {
+    name == "size" || is_known_property(name)
+}
+
+/**
+The properties whose initial value CSS 2.1 leaves to the user agent, and which
+the `SelectHandler` is asked for. libcss picks `voice-family` itself, as aural
+properties have no typed values yet
+*/
+#[deriving_eq]
+pub enum Property {
+    PropertyColor,
+    PropertyFontFamily,
+    PropertyQuotes
+}
+
+/** A property and its value, as written in a declaration block */
+#[deriving_eq]
+pub struct PropertyDeclaration {
+    value: PropertyValue,
+    important: bool
+}
+
+/**
+The value of one longhand property. Shorthands are expanded into their
+longhands when parsed. Properties without a representation in `values` yet
+are kept as CSS text, even when libcss would reject it.
+*/
+#[deriving_eq]
+pub enum PropertyValue {
+    MarginTopValue(CSSValue<CSSMargin>),
+    MarginRightValue(CSSValue<CSSMargin>),
+    MarginBottomValue(CSSValue<CSSMargin>),
+    MarginLeftValue(CSSValue<CSSMargin>),
+    PaddingTopValue(CSSValue<CSSPadding>),
+    PaddingRightValue(CSSValue<CSSPadding>),
+    PaddingBottomValue(CSSValue<CSSPadding>),
+    PaddingLeftValue(CSSValue<CSSPadding>),
+    BorderTopWidthValue(CSSValue<CSSBorderWidth>),
+    BorderRightWidthValue(CSSValue<CSSBorderWidth>),
+    BorderBottomWidthValue(CSSValue<CSSBorderWidth>),
+    BorderLeftWidthValue(CSSValue<CSSBorderWidth>),
+    BorderTopColorValue(CSSValue<CSSBorderColor>),
+    BorderRightColorValue(CSSValue<CSSBorderColor>),
+    BorderBottomColorValue(CSSValue<CSSBorderColor>),
+    BorderLeftColorValue(CSSValue<CSSBorderColor>),
+    BorderTopStyleValue(CSSValue<CSSBorderStyle>),
+    BorderRightStyleValue(CSSValue<CSSBorderStyle>),
+    BorderBottomStyleValue(CSSValue<CSSBorderStyle>),
+    BorderLeftStyleValue(CSSValue<CSSBorderStyle>),
+    DisplayValue(CSSValue<CSSDisplay>),
+    PositionValue(CSSValue<CSSPosition>),
+    TopValue(CSSValue<CSSTop>),
+    RightValue(CSSValue<CSSRight>),
+    BottomValue(CSSValue<CSSBottom>),
+    LeftValue(CSSValue<CSSLeft>),
+    FloatValue(CSSValue<CSSFloat>),
+    DirectionValue(CSSValue<CSSDirection>),
+    WidthValue(CSSValue<CSSWidth>),
+    HeightValue(CSSValue<CSSHeight>),
+    LineHeightValue(CSSValue<CSSLineHeight>),
+    VerticalAlignValue(CSSValue<CSSVerticalAlign>),
+    OverflowValue(CSSValue<CSSOverflow>),
+    VisibilityValue(CSSValue<CSSVisibility>),
+    QuotesValue(CSSValue<CSSQuotes>),
+    PageBreakBeforeValue(CSSValue<CSSPageBreak>),
+    PageBreakAfterValue(CSSValue<CSSPageBreak>),
+    PageBreakInsideValue(CSSValue<CSSPageBreakInside>),
+    OrphansValue(CSSValue<uint>),
+    WidowsValue(CSSValue<uint>),
+    /// Only found in `@page` rules
+    SizeValue(CSSValue<CSSPageSize>),
+    ColorValue(CSSValue<CSSColor>),
+    BackgroundColorValue(CSSValue<CSSBackgroundColor>),
+    BackgroundImageValue(CSSValue<CSSBackgroundImage>),
+    BackgroundRepeatValue(CSSValue<CSSBackgroundRepeat>),
+    BackgroundAttachmentValue(CSSValue<CSSBackgroundAttachment>),
+    /// The horizontal and vertical positions
+    BackgroundPositionValue(CSSValue<(CSSBackgroundPosition, CSSBackgroundPosition)>),
+    FontFamilyValue(CSSValue<~[CSSFontFamily]>),
+    FontStyleValue(CSSValue<CSSFontStyle>),
+    FontWeightValue(CSSValue<CSSFontWeight>),
+    FontSizeValue(CSSValue<CSSFontSize>),
+    TextAlignValue(CSSValue<CSSTextAlign>),
+    TextDecorationValue(CSSValue<~[CSSTextDecoration]>),
+    TextTransformValue(CSSValue<CSSTextTransform>),
+    /// The name of a known property and its value as CSS text
+    UnparsedValue(~str, ~str)
+}
+
+impl PropertyValue {
+    /** The name of the property this is a value of */
+    fn name(&self) -> ~str {
+        match *self {
+            MarginTopValue(_) => ~"margin-top",
+            MarginRightValue(_) => ~"margin-right",
+            MarginBottomValue(_) => ~"margin-bottom",
+            MarginLeftValue(_) => ~"margin-left",
+            PaddingTopValue(_) => ~"padding-top",
+            PaddingRightValue(_) => ~"padding-right",
+            PaddingBottomValue(_) => ~"padding-bottom",
+            PaddingLeftValue(_) => ~"padding-left",
+            BorderTopWidthValue(_) => ~"border-top-width",
+            BorderRightWidthValue(_) => ~"border-right-width",
+            BorderBottomWidthValue(_) => ~"border-bottom-width",
+            BorderLeftWidthValue(_) => ~"border-left-width",
+            BorderTopColorValue(_) => ~"border-top-color",
+            BorderRightColorValue(_) => ~"border-right-color",
+            BorderBottomColorValue(_) => ~"border-bottom-color",
+            BorderLeftColorValue(_) => ~"border-left-color",
+            BorderTopStyleValue(_) => ~"border-top-style",
+            BorderRightStyleValue(_) => ~"border-right-style",
+            BorderBottomStyleValue(_) => ~"border-bottom-style",
+            BorderLeftStyleValue(_) => ~"border-left-style",
+            DisplayValue(_) => ~"display",
+            PositionValue(_) => ~"position",
+            TopValue(_) => ~"top",
+            RightValue(_) => ~"right",
+            BottomValue(_) => ~"bottom",
+            LeftValue(_) => ~"left",
+            FloatValue(_) => ~"float",
+            DirectionValue(_) => ~"direction",
+            WidthValue(_) => ~"width",
+            HeightValue(_) => ~"height",
+            LineHeightValue(_) => ~"line-height",
+            VerticalAlignValue(_) => ~"vertical-align",
+            OverflowValue(_) => ~"overflow",
+            VisibilityValue(_) => ~"visibility",
+            QuotesValue(_) => ~"quotes",
+            PageBreakBeforeValue(_) => ~"page-break-before",
+            PageBreakAfterValue(_) => ~"page-break-after",
+            PageBreakInsideValue(_) => ~"page-break-inside",
+            OrphansValue(_) => ~"orphans",
+            WidowsValue(_) => ~"widows",
+            SizeValue(_) => ~"size",
+            ColorValue(_) => ~"color",
+            BackgroundColorValue(_) => ~"background-color",
+            BackgroundImageValue(_) => ~"background-image",
+            BackgroundRepeatValue(_) => ~"background-repeat",
+            BackgroundAttachmentValue(_) => ~"background-attachment",
+            BackgroundPositionValue(_) => ~"background-position",
+            FontFamilyValue(_) => ~"font-family",
+            FontStyleValue(_) => ~"font-style",
+            FontWeightValue(_) => ~"font-weight",
+            FontSizeValue(_) => ~"font-size",
+            TextAlignValue(_) => ~"text-align",
+            TextDecorationValue(_) => ~"text-decoration",
+            TextTransformValue(_) => ~"text-transform",
+            UnparsedValue(ref name, _) => copy *name
+        }
+    }
+}
+
+/**
+Convert a declaration from the syntax layer. Shorthands produce one
+declaration per longhand. Values that don't fit the property's grammar
+produce none, as libcss ignores them too.
+*/
+pub fn parse_declaration(declaration: &RawDeclaration, base: &Url) -> ~[PropertyDeclaration] {
+    let values = parse_property_value(declaration.name, declaration.value, base);
+    do values.map |value| {
+        PropertyDeclaration {
+            value: copy *value,
+            important: declaration.important
+        }
+    }
+}
+
+fn parse_property_value(name: &str, value: &[SourceToken], base: &Url) -> ~[PropertyValue] {
+    let cs = components(value);
+    let inherit = cs.len() == 1 && keyword(cs[0]) == Some(~"inherit");
+
+    macro_rules! longhand (
+        ($variant:ident, $parse:expr) => (
+            if inherit {
+                ~[$variant(Inherit)]
+            } else if cs.len() == 1 {
+                match $parse(cs[0]) {
+                    Some(v) => ~[$variant(Specified(v))],
+                    None => ~[]
+                }
+            } else {
+                ~[]
+            }
+        )
+    )
+
+    // These colors are only known at selection time, so they are kept as
+    // text for libcss to resolve
+    if is_color_longhand(name) && cs.len() == 1 {
+        match keyword(cs[0]) {
+            Some(ref k) if *k == ~"currentcolor" || is_system_color(*k) => {
+                return ~[UnparsedValue(name.to_owned(), tokens_to_str(value))];
+            }
+            _ => ()
+        }
+    }
+
+    // CSSTopPercentage has no room for the percentage, so it is kept as text
+    if name == "top" && cs.len() == 1 && parse_percentage(cs[0]).is_some() {
+        return ~[UnparsedValue(name.to_owned(), tokens_to_str(value))];
+    }
+
+    match name {
+        "margin-top" => longhand!(MarginTopValue, parse_margin),
+        "margin-right" => longhand!(MarginRightValue, parse_margin),
+        "margin-bottom" => longhand!(MarginBottomValue, parse_margin),
+        "margin-left" => longhand!(MarginLeftValue, parse_margin),
+        "padding-top" => longhand!(PaddingTopValue, parse_padding),
+        "padding-right" => longhand!(PaddingRightValue, parse_padding),
+        "padding-bottom" => longhand!(PaddingBottomValue, parse_padding),
+        "padding-left" => longhand!(PaddingLeftValue, parse_padding),
+        "border-top-width" => longhand!(BorderTopWidthValue, parse_border_width),
+        "border-right-width" => longhand!(BorderRightWidthValue, parse_border_width),
+        "border-bottom-width" => longhand!(BorderBottomWidthValue, parse_border_width),
+        "border-left-width" => longhand!(BorderLeftWidthValue, parse_border_width),
+        "border-top-color" => longhand!(BorderTopColorValue, parse_border_color),
+        "border-right-color" => longhand!(BorderRightColorValue, parse_border_color),
+        "border-bottom-color" => longhand!(BorderBottomColorValue, parse_border_color),
+        "border-left-color" => longhand!(BorderLeftColorValue, parse_border_color),
+        "border-top-style" => longhand!(BorderTopStyleValue, parse_border_style),
+        "border-right-style" => longhand!(BorderRightStyleValue, parse_border_style),
+        "border-bottom-style" => longhand!(BorderBottomStyleValue, parse_border_style),
+        "border-left-style" => longhand!(BorderLeftStyleValue, parse_border_style),
+        "display" => longhand!(DisplayValue, parse_display),
+        "position" => longhand!(PositionValue, parse_position),
+        "top" => longhand!(TopValue, parse_top),
+        "right" => longhand!(RightValue, parse_right),
+        "bottom" => longhand!(BottomValue, parse_bottom),
+        "left" => longhand!(LeftValue, parse_left),
+        "float" => longhand!(FloatValue, parse_float),
+        "direction" => longhand!(DirectionValue, parse_direction),
+        "width" => longhand!(WidthValue, parse_width),
+        "height" => longhand!(HeightValue, parse_height),
+        "line-height" => longhand!(LineHeightValue, parse_line_height),
+        "vertical-align" => longhand!(VerticalAlignValue, parse_vertical_align),
+        "overflow" => longhand!(OverflowValue, parse_overflow),
+        "visibility" => longhand!(VisibilityValue, parse_visibility),
+        "page-break-before" => longhand!(PageBreakBeforeValue, parse_page_break),
+        "page-break-after" => longhand!(PageBreakAfterValue, parse_page_break),
+        "page-break-inside" => longhand!(PageBreakInsideValue, parse_page_break_inside),
+        "orphans" => longhand!(OrphansValue, parse_positive_integer),
+        "widows" => longhand!(WidowsValue, parse_positive_integer),
+        "color" => longhand!(ColorValue, |c| parse_color(c).map(|c| CSSColorColor(*c))),
+        "background-color" => longhand!(BackgroundColorValue, parse_background_color),
+        "background-image" => longhand!(BackgroundImageValue, |c| parse_background_image(c, base)),
+        "background-repeat" => longhand!(BackgroundRepeatValue, parse_background_repeat),
+        "background-attachment" => {
+            longhand!(BackgroundAttachmentValue, parse_background_attachment)
+        }
+        "font-style" => longhand!(FontStyleValue, parse_font_style),
+        "font-weight" => longhand!(FontWeightValue, parse_font_weight),
+        "font-size" => longhand!(FontSizeValue, parse_font_size),
+        "text-align" => longhand!(TextAlignValue, parse_text_align),
+        "text-transform" => longhand!(TextTransformValue, parse_text_transform),
+
+        "background-position" => {
+            if inherit {
+                ~[BackgroundPositionValue(Inherit)]
+            } else {
+                match parse_background_position(cs) {
+                    Some(v) => ~[BackgroundPositionValue(Specified(v))],
+                    None => ~[]
+                }
+            }
+        }
+        "font-family" => {
+            if inherit {
+                ~[FontFamilyValue(Inherit)]
+            } else {
+                match parse_font_family(cs) {
+                    Some(v) => ~[FontFamilyValue(Specified(v))],
+                    None => ~[]
+                }
+            }
+        }
+        "quotes" => {
+            if inherit {
+                ~[QuotesValue(Inherit)]
+            } else {
+                match parse_quotes(cs) {
+                    Some(v) => ~[QuotesValue(Specified(v))],
+                    None => ~[]
+                }
+            }
+        }
+        "size" => {
+            if inherit {
+                ~[SizeValue(Inherit)]
+            } else {
+                match parse_page_size(cs) {
+                    Some(v) => ~[SizeValue(Specified(v))],
+                    None => ~[]
+                }
+            }
+        }
+        "text-decoration" => {
+            if inherit {
+                ~[TextDecorationValue(Inherit)]
+            } else {
+                match parse_text_decoration(cs) {
+                    Some(v) => ~[TextDecorationValue(Specified(v))],
+                    None => ~[]
+                }
+            }
+        }
+
+        "margin" => {
+            expand_box(cs, inherit, parse_margin, MarginTopValue, MarginRightValue,
+                       MarginBottomValue, MarginLeftValue)
+        }
+        "padding" => {
+            expand_box(cs, inherit, parse_padding, PaddingTopValue, PaddingRightValue,
+                       PaddingBottomValue, PaddingLeftValue)
+        }
+        "border-width" => {
+            expand_box(cs, inherit, parse_border_width, BorderTopWidthValue,
+                       BorderRightWidthValue, BorderBottomWidthValue, BorderLeftWidthValue)
+        }
+        "border-color" => {
+            expand_box(cs, inherit, parse_border_color, BorderTopColorValue,
+                       BorderRightColorValue, BorderBottomColorValue, BorderLeftColorValue)
+        }
+        "border-style" => {
+            expand_box(cs, inherit, parse_border_style, BorderTopStyleValue,
+                       BorderRightStyleValue, BorderBottomStyleValue, BorderLeftStyleValue)
+        }
+        "border-top" => {
+            expand_border_side(cs, inherit, BorderTopWidthValue, BorderTopStyleValue,
+                               BorderTopColorValue)
+        }
+        "border-right" => {
+            expand_border_side(cs, inherit, BorderRightWidthValue, BorderRightStyleValue,
+                               BorderRightColorValue)
+        }
+        "border-bottom" => {
+            expand_border_side(cs, inherit, BorderBottomWidthValue, BorderBottomStyleValue,
+                               BorderBottomColorValue)
+        }
+        "border-left" => {
+            expand_border_side(cs, inherit, BorderLeftWidthValue, BorderLeftStyleValue,
+                               BorderLeftColorValue)
+        }
+        "border" => {
+            let mut values = ~[];
+            values.push_all(expand_border_side(cs, inherit, BorderTopWidthValue,
+                                               BorderTopStyleValue, BorderTopColorValue));
+            values.push_all(expand_border_side(cs, inherit, BorderRightWidthValue,
+                                               BorderRightStyleValue, BorderRightColorValue));
+            values.push_all(expand_border_side(cs, inherit, BorderBottomWidthValue,
+                                               BorderBottomStyleValue, BorderBottomColorValue));
+            values.push_all(expand_border_side(cs, inherit, BorderLeftWidthValue,
+                                               BorderLeftStyleValue, BorderLeftColorValue));
+            values
+        }
+        "background" => expand_background(cs, inherit, base),
+        "font" => expand_font(cs, inherit),
+
+        _ => ~[UnparsedValue(name.to_owned(), tokens_to_str(value))]
+    }
+}
+
+/** Write tokens back out as CSS text */
+pub fn tokens_to_str(tokens: &[SourceToken]) -> ~str {
+    let mut text = ~"";
+    for tokens.each |token| {
+        str::push_str(&mut text, token.token.to_str());
+    }
+    text
+}
+
+/**
+Convert the descriptors of an `@font-face` block. Invalid descriptors are
+ignored, leaving their initial values; a block without a valid
+`font-family` and `src` gives `None`.
+*/
+pub fn parse_font_face(declarations: &[RawDeclaration], base: &Url) -> Option<FontFaceRule> {
+    let mut family = None;
+    let mut sources = None;
+    let mut weight = CSSFontWeightNormal;
+    let mut style = CSSFontStyleNormal;
+    let mut unicode_range = ~[(0, 0x10FFFF)];
+    for declarations.each |declaration| {
+        let cs = components(declaration.value);
+        match declaration.name.to_lower() {
+            ~"font-family" => {
+                // A single name, not a list or a generic family
+                match parse_font_family(cs) {
+                    Some(families) if families.len() == 1 => {
+                        match families[0] {
+                            CSSFontFamilyFamilyName(ref name) => family = Some(copy *name),
+                            _ => ()
+                        }
+                    }
+                    _ => ()
+                }
+            }
+            ~"src" => {
+                match parse_font_face_sources(cs, base) {
+                    Some(list) => sources = Some(list),
+                    None => ()
+                }
+            }
+            ~"font-weight" if cs.len() == 1 => {
+                match parse_font_weight(cs[0]) {
+                    // Relative weights make no sense for a single face
+                    Some(CSSFontWeightBolder) | Some(CSSFontWeightLighter) | None => (),
+                    Some(value) => weight = value
+                }
+            }
+            ~"font-style" if cs.len() == 1 => {
+                for parse_font_style(cs[0]).each |&value| {
+                    style = value;
+                }
+            }
+            ~"unicode-range" => {
+                match parse_unicode_ranges(cs) {
+                    Some(ranges) => unicode_range = ranges,
+                    None => ()
+                }
+            }
+            _ => ()
+        }
+    }
+    match (family, sources) {
+        (Some(family), Some(sources)) => Some(FontFaceRule {
+            family: family,
+            sources: sources,
+            weight: weight,
+            style: style,
+            unicode_range: unicode_range
+        }),
+        _ => None
+    }
+}
+
+/** `url(...) format(...)` and `local(...)` entries, separated by commas */
+fn parse_font_face_sources(cs: &[~[Token]], base: &Url) -> Option<~[FontFaceSource]> {
+    let mut sources = ~[];
+    for split_components(cs).each |entry| {
+        let source = match entry.len() {
+            1 | 2 => parse_font_face_source(entry[0], entry.slice(1, entry.len()), base),
+            _ => None
+        };
+        match source {
+            Some(source) => sources.push(source),
+            None => return None
+        }
+    }
+    Some(sources)
+}
+
+fn parse_font_face_source(c: &[Token], hints: &[~[Token]], base: &Url) -> Option<FontFaceSource> {
+    match c {
+        [Uri(ref url)] => {
+            let resolved = resolve_relative(base.to_str(), *url).get_or_default(copy *url);
+            let url = match url_from_str(resolved) {
+                Ok(url) => url,
+                Err(_) => return None
+            };
+            let mut formats = ~[];
+            for hints.each |hint| {
+                let hint: &[Token] = *hint;
+                match hint {
+                    [Function(ref name), ..args] if name.to_lower() == ~"format" => {
+                        for args.each |arg| {
+                            match *arg {
+                                QuotedString(ref format) => formats.push(format.to_lower()),
+                                Delim(',') | RightParen => (),
+                                _ => return None
+                            }
+                        }
+                    }
+                    _ => return None
+                }
+            }
+            Some(FontFaceSourceUrl(url, formats))
+        }
+        [Function(ref name), ..args] if name.to_lower() == ~"local" && hints.is_empty() => {
+            match args {
+                [QuotedString(ref font), RightParen] => Some(FontFaceSourceLocal(copy *font)),
+                _ => {
+                    // An unquoted name is a series of identifiers
+                    let mut names = ~[];
+                    for args.each |arg| {
+                        match *arg {
+                            Ident(ref name) => names.push(copy *name),
+                            RightParen => (),
+                            _ => return None
+                        }
+                    }
+                    if names.is_empty() {
+                        None
+                    } else {
+                        Some(FontFaceSourceLocal(str::connect(names, " ")))
+                    }
+                }
+            }
+        }
+        _ => None
+    }
+}
+
+fn parse_unicode_ranges(cs: &[~[Token]]) -> Option<~[(u32, u32)]> {
+    let mut ranges = ~[];
+    for split_components(cs).each |entry| {
+        if entry.len() != 1 {
+            return None;
+        }
+        let c: &[Token] = entry[0];
+        let range = match c {
+            [UnicodeRange(ref range)] => parse_unicode_range(*range),
+            _ => None
+        };
+        match range {
+            Some(range) => ranges.push(range),
+            None => return None
+        }
+    }
+    Some(ranges)
+}
+
+/** `U+26`, `U+0-7F` or `U+4??`, where `?` stands for any hex digit */
+fn parse_unicode_range(range: &str) -> Option<(u32, u32)> {
+    let range = range.slice(2, range.len());
+    let (first, last) = match str::find_char(range, '-') {
+        Some(i) => (range.slice(0, i).to_owned(), range.slice(i + 1, range.len()).to_owned()),
+        None => (str::replace(range, "?", "0"), str::replace(range, "?", "F"))
+    };
+    match (uint::from_str_radix(first, 16), uint::from_str_radix(last, 16)) {
+        (Some(first), Some(last)) if first <= last && last <= 0x10FFFF => {
+            Some((first as u32, last as u32))
+        }
+        _ => None
+    }
+}
+
+/** Group components into the comma-separated entries of a list */
+fn split_components(cs: &[~[Token]]) -> ~[~[~[Token]]] {
+    let mut entries = ~[];
+    let mut entry = ~[];
+    for cs.each |c| {
+        if *c == ~[Delim(',')] {
+            entries.push(entry);
+            entry = ~[];
+        } else {
+            entry.push(copy *c);
+        }
+    }
+    entries.push(entry);
+    entries
+}
+
+/**
+Split a value into its component values, dropping whitespace. A function
+and its arguments form one component; everything else is a single token.
+*/
+fn components(value: &[SourceToken]) -> ~[~[Token]] {
+    let mut result = ~[];
+    let mut i = 0;
+    while i < value.len() {
+        match value[i].token {
+            Whitespace => i += 1,
+            Function(_) => {
+                let mut component = ~[];
+                let mut depth = 0;
+                while i < value.len() {
+                    let token = copy value[i].token;
+                    i += 1;
+                    match token {
+                        Function(_) | LeftParen => depth += 1,
+                        RightParen => depth -= 1,
+                        Whitespace => loop,
+                        _ => ()
+                    }
+                    component.push(token);
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                result.push(component);
+            }
+            _ => {
+                result.push(~[copy value[i].token]);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+fn keyword(component: &[Token]) -> Option<~str> {
+    match component {
+        [Ident(ref name)] => Some(name.to_lower()),
+        _ => None
+    }
+}
+
+/** A length, with absolute units converted to points */
+pub fn parse_length(component: &[Token]) -> Option<Length> {
+    match component {
+        [Number(0.0)] => Some(Px(0.0)),
+        [Dimension(value, ref unit)] => {
+            match unit.to_lower() {
+                ~"px" => Some(Px(value)),
+                ~"em" => Some(Em(value)),
+                ~"ex" => Some(Ex(value)),
+                ~"pt" => Some(Pt(value)),
+                ~"pc" => Some(Pt(value * 12.0)),
+                ~"in" => Some(Pt(value * 72.0)),
+                ~"cm" => Some(Pt(value * 72.0 / 2.54)),
+                ~"mm" => Some(Pt(value * 72.0 / 25.4)),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+fn parse_non_negative_length(component: &[Token]) -> Option<Length> {
+    match component {
+        [Dimension(value, _)] if value < 0.0 => None,
+        _ => parse_length(component)
+    }
+}
+
+fn parse_percentage(component: &[Token]) -> Option<float> {
+    match component {
+        [Percentage(value)] => Some(value),
+        _ => None
+    }
+}
+
+fn parse_non_negative_percentage(component: &[Token]) -> Option<float> {
+    parse_percentage(component).chain(|p| if p < 0.0 { None } else { Some(p) })
+}
+
+/** A `<color>`: a keyword, a hex color or an `rgb()`-style function */
+fn parse_color(component: &[Token]) -> Option<Color> {
+    match component {
+        [Ident(ref name)] => parse_color_keyword(*name),
+        [Hash(ref hex)] => parse_hex_color(*hex),
+        [Function(ref name), ..args] => {
+            let mut numbers = ~[];
+            let mut expect_comma = false;
+            for args.each |arg| {
+                match *arg {
+                    Delim(',') if expect_comma => expect_comma = false,
+                    Number(v) if !expect_comma => {
+                        numbers.push((v, false));
+                        expect_comma = true;
+                    }
+                    Percentage(v) if !expect_comma => {
+                        numbers.push((v, true));
+                        expect_comma = true;
+                    }
+                    RightParen if expect_comma => (),
+                    _ => return None
+                }
+            }
+            parse_color_function(name.to_lower(), numbers)
+        }
+        _ => None
+    }
+}
+
+fn is_color_longhand(name: &str) -> bool {
+    match name {
+        "color" | "background-color" | "border-top-color" | "border-right-color" |
+        "border-bottom-color" | "border-left-color" => true,
+        _ => false
+    }
+}
+
+/** Whether `name`, which must be lower case, is one of the CSS2 system colors */
+fn is_system_color(name: &str) -> bool {
+    SYSTEM_COLORS.any(|color| str::eq_slice(*color, name))
+}
+
+const SYSTEM_COLORS: &'static [&'static str] = &[
+    "activeborder", "activecaption", "appworkspace", "background", "buttonface",
+    "buttonhighlight", "buttonshadow", "buttontext", "captiontext", "graytext", "highlight",
+    "highlighttext", "inactiveborder", "inactivecaption", "inactivecaptiontext",
+    "infobackground", "infotext", "menu", "menutext", "scrollbar", "threeddarkshadow",
+    "threedface", "threedhighlight", "threedlightshadow", "threedshadow", "window",
+    "windowframe", "windowtext"
+];
+
+/** The digits of a hex color, without its `#` */
+pub fn parse_hex_color(hex: &str) -> Option<Color> {
+    let mut digits = ~[];
+    for str::each_char(hex) |c| {
+        match char::to_digit(c, 16) {
+            Some(d) => digits.push(d as u8),
+            None => return None
+        }
+    }
+    match digits.len() {
+        3 => Some(rgb(digits[0] * 17, digits[1] * 17, digits[2] * 17)),
+        6 => Some(rgb(digits[0] * 16 + digits[1], digits[2] * 16 + digits[3],
+                      digits[4] * 16 + digits[5])),
+        _ => None
+    }
+}
+
+/** The arguments of `rgb()`, `rgba()`, `hsl()` or `hsla()`, and whether each is a percentage */
+fn parse_color_function(name: &str, args: &[(float, bool)]) -> Option<Color> {
+    fn clamp(value: float, max: float) -> float {
+        if value < 0.0 { 0.0 } else if value > max { max } else { value }
+    }
+    fn channel(arg: (float, bool)) -> u8 {
+        let (value, percent) = arg;
+        let value = if percent { value * 255.0 / 100.0 } else { value };
+        (clamp(value, 255.0) + 0.5) as u8
+    }
+    fn unit(value: float) -> float {
+        clamp(value, 1.0)
+    }
+    match (name, args.len()) {
+        ("rgb", 3) => Some(rgb(channel(args[0]), channel(args[1]), channel(args[2]))),
+        ("rgba", 4) => {
+            let (alpha, _) = args[3];
+            Some(rgba(channel(args[0]), channel(args[1]), channel(args[2]), unit(alpha)))
+        }
+        ("hsl", 3) | ("hsla", 4) => {
+            let (h, _) = args[0];
+            let (s, s_percent) = args[1];
+            let (l, l_percent) = args[2];
+            if !s_percent || !l_percent {
+                return None;
+            }
+            let h = ((h % 360.0) + 360.0) % 360.0;
+            let alpha = if args.len() == 4 { let (a, _) = args[3]; unit(a) } else { 1.0 };
+            Some(hsla(h, unit(s / 100.0), unit(l / 100.0), alpha))
+        }
+        _ => None
+    }
+}
+
+fn parse_margin(c: &[Token]) -> Option<CSSMargin> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSMarginAuto),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSMarginLength(l)),
+            None => parse_percentage(c).map(|p| CSSMarginPercentage(*p))
+        }
+    }
+}
+
+fn parse_padding(c: &[Token]) -> Option<CSSPadding> {
+    match parse_non_negative_length(c) {
+        Some(l) => Some(CSSPaddingLength(l)),
+        None => parse_non_negative_percentage(c).map(|p| CSSPaddingPercentage(*p))
+    }
+}
+
+fn parse_border_width(c: &[Token]) -> Option<CSSBorderWidth> {
+    match keyword(c) {
+        Some(~"thin") => Some(CSSBorderWidthThin),
+        Some(~"medium") => Some(CSSBorderWidthMedium),
+        Some(~"thick") => Some(CSSBorderWidthThick),
+        Some(_) => None,
+        None => parse_non_negative_length(c).map(|l| CSSBorderWidthLength(*l))
+    }
+}
+
+fn parse_border_color(c: &[Token]) -> Option<CSSBorderColor> {
+    match keyword(c) {
+        Some(~"transparent") => Some(CSSBorderColorTransparent),
+        _ => parse_color(c).map(|c| CSSBorderColorColor(*c))
+    }
+}
+
+fn parse_border_style(c: &[Token]) -> Option<CSSBorderStyle> {
+    match keyword(c) {
+        Some(~"none") => Some(CSSBorderStyleNone),
+        Some(~"hidden") => Some(CSSBorderStyleHidden),
+        Some(~"dotted") => Some(CSSBorderStyleDotted),
+        Some(~"dashed") => Some(CSSBorderStyleDashed),
+        Some(~"solid") => Some(CSSBorderStyleSolid),
+        Some(~"double") => Some(CSSBorderStyleDouble),
+        Some(~"groove") => Some(CSSBorderStyleGroove),
+        Some(~"ridge") => Some(CSSBorderStyleRidge),
+        Some(~"inset") => Some(CSSBorderStyleInset),
+        Some(~"outset") => Some(CSSBorderStyleOutset),
+        _ => None
+    }
+}
+
+fn parse_display(c: &[Token]) -> Option<CSSDisplay> {
+    match keyword(c) {
+        Some(~"inline") => Some(CSSDisplayInline),
+        Some(~"block") => Some(CSSDisplayBlock),
+        Some(~"list-item") => Some(CSSDisplayListItem),
+        Some(~"inline-block") => Some(CSSDisplayInlineBlock),
+        Some(~"table") => Some(CSSDisplayTable),
+        Some(~"inline-table") => Some(CSSDisplayInlineTable),
+        Some(~"table-row-group") => Some(CSSDisplayTableRowGroup),
+        Some(~"table-header-group") => Some(CSSDisplayTableHeaderGroup),
+        Some(~"table-footer-group") => Some(CSSDisplayTableFooterGroup),
+        Some(~"table-row") => Some(CSSDisplayTableRow),
+        Some(~"table-column-group") => Some(CSSDisplayTableColumnGroup),
+        Some(~"table-column") => Some(CSSDisplayTableColumn),
+        Some(~"table-cell") => Some(CSSDisplayTableCell),
+        Some(~"table-caption") => Some(CSSDisplayTableCaption),
+        Some(~"none") => Some(CSSDisplayNone),
+        _ => None
+    }
+}
+
+fn parse_position(c: &[Token]) -> Option<CSSPosition> {
+    match keyword(c) {
+        Some(~"static") => Some(CSSPositionStatic),
+        Some(~"relative") => Some(CSSPositionRelative),
+        Some(~"absolute") => Some(CSSPositionAbsolute),
+        Some(~"fixed") => Some(CSSPositionFixed),
+        _ => None
+    }
+}
+
+fn parse_top(c: &[Token]) -> Option<CSSTop> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSTopAuto),
+        Some(_) => None,
+        None => parse_length(c).map(|l| CSSTopLength(*l))
+    }
+}
+
+fn parse_right(c: &[Token]) -> Option<CSSRight> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSRightAuto),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSRightLength(l)),
+            None => parse_percentage(c).map(|p| CSSRightPercentage(*p))
+        }
+    }
+}
+
+fn parse_bottom(c: &[Token]) -> Option<CSSBottom> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSBottomAuto),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSBottomLength(l)),
+            None => parse_percentage(c).map(|p| CSSBottomPercentage(*p))
+        }
+    }
+}
+
+fn parse_left(c: &[Token]) -> Option<CSSLeft> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSLeftAuto),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSLeftLength(l)),
+            None => parse_percentage(c).map(|p| CSSLeftPercentage(*p))
+        }
+    }
+}
+
+fn parse_float(c: &[Token]) -> Option<CSSFloat> {
+    match keyword(c) {
+        Some(~"left") => Some(CSSFloatLeft),
+        Some(~"right") => Some(CSSFloatRight),
+        Some(~"none") => Some(CSSFloatNone),
+        _ => None
+    }
+}
+
+fn parse_direction(c: &[Token]) -> Option<CSSDirection> {
+    match keyword(c) {
+        Some(~"ltr") => Some(CSSDirectionLtr),
+        Some(~"rtl") => Some(CSSDirectionRtl),
+        _ => None
+    }
+}
+
+fn parse_width(c: &[Token]) -> Option<CSSWidth> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSWidthAuto),
+        Some(_) => None,
+        None => match parse_non_negative_length(c) {
+            Some(l) => Some(CSSWidthLength(l)),
+            None => parse_non_negative_percentage(c).map(|p| CSSWidthPercentage(*p))
+        }
+    }
+}
+
+fn parse_height(c: &[Token]) -> Option<CSSHeight> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSHeightAuto),
+        Some(_) => None,
+        None => match parse_non_negative_length(c) {
+            Some(l) => Some(CSSHeightLength(l)),
+            None => parse_non_negative_percentage(c).map(|p| CSSHeightPercentage(*p))
+        }
+    }
+}
+
+fn parse_line_height(c: &[Token]) -> Option<CSSLineHeight> {
+    match c {
+        [Number(n)] if n >= 0.0 => Some(CSSLineHeightNumber(n)),
+        _ => match keyword(c) {
+            Some(~"normal") => Some(CSSLineHeightNormal),
+            Some(_) => None,
+            None => match parse_non_negative_length(c) {
+                Some(l) => Some(CSSLineHeightLength(l)),
+                None => parse_non_negative_percentage(c).map(|p| CSSLineHeightPercentage(*p))
+            }
+        }
+    }
+}
+
+fn parse_vertical_align(c: &[Token]) -> Option<CSSVerticalAlign> {
+    match keyword(c) {
+        Some(~"baseline") => Some(CSSVerticalAlignBaseline),
+        Some(~"sub") => Some(CSSVerticalAlignSub),
+        Some(~"super") => Some(CSSVerticalAlignSuper),
+        Some(~"top") => Some(CSSVerticalAlignTop),
+        Some(~"text-top") => Some(CSSVerticalAlignTextTop),
+        Some(~"middle") => Some(CSSVerticalAlignMiddle),
+        Some(~"bottom") => Some(CSSVerticalAlignBottom),
+        Some(~"text-bottom") => Some(CSSVerticalAlignTextBottom),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSVerticalAlignLength(l)),
+            None => parse_percentage(c).map(|p| CSSVerticalAlignPercentage(*p))
+        }
+    }
+}
+
+fn parse_overflow(c: &[Token]) -> Option<CSSOverflow> {
+    match keyword(c) {
+        Some(~"visible") => Some(CSSOverflowVisible),
+        Some(~"hidden") => Some(CSSOverflowHidden),
+        Some(~"scroll") => Some(CSSOverflowScroll),
+        Some(~"auto") => Some(CSSOverflowAuto),
+        _ => None
+    }
+}
+
+fn parse_visibility(c: &[Token]) -> Option<CSSVisibility> {
+    match keyword(c) {
+        Some(~"visible") => Some(CSSVisibilityVisible),
+        Some(~"hidden") => Some(CSSVisibilityHidden),
+        Some(~"collapse") => Some(CSSVisibilityCollapse),
+        _ => None
+    }
+}
+
+/** `none`, or pairs of strings to open and close quotes with */
+fn parse_quotes(cs: &[~[Token]]) -> Option<CSSQuotes> {
+    if cs.len() == 1 && keyword(cs[0]) == Some(~"none") {
+        return Some(CSSQuotesNone);
+    }
+    if cs.is_empty() || cs.len() % 2 != 0 {
+        return None;
+    }
+    let mut pairs = ~[];
+    for uint::range(0, cs.len() / 2) |i| {
+        match (copy cs[2 * i], copy cs[2 * i + 1]) {
+            ([QuotedString(open)], [QuotedString(close)]) => pairs.push((open, close)),
+            _ => return None
+        }
+    }
+    Some(CSSQuotesPairs(pairs))
+}
+
+fn parse_page_break(c: &[Token]) -> Option<CSSPageBreak> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSPageBreakAuto),
+        Some(~"always") => Some(CSSPageBreakAlways),
+        Some(~"avoid") => Some(CSSPageBreakAvoid),
+        Some(~"left") => Some(CSSPageBreakLeft),
+        Some(~"right") => Some(CSSPageBreakRight),
+        _ => None
+    }
+}
+
+fn parse_page_break_inside(c: &[Token]) -> Option<CSSPageBreakInside> {
+    match keyword(c) {
+        Some(~"auto") => Some(CSSPageBreakInsideAuto),
+        Some(~"avoid") => Some(CSSPageBreakInsideAvoid),
+        _ => None
+    }
+}
+
+fn parse_positive_integer(c: &[Token]) -> Option<uint> {
+    match c {
+        [Number(n)] if n >= 1.0 && n == (n as uint) as float => Some(n as uint),
+        _ => None
+    }
+}
+
+/** `auto`, one or two lengths, or a named size and/or an orientation */
+fn parse_page_size(cs: &[~[Token]]) -> Option<CSSPageSize> {
+    let lengths = cs.map(|c| parse_non_negative_length(*c));
+    match lengths {
+        [Some(side)] => return Some(CSSPageSizeLength(side, side)),
+        [Some(width), Some(height)] => return Some(CSSPageSizeLength(width, height)),
+        _ => ()
+    }
+    let keywords = cs.map(|c| keyword(*c));
+    if keywords.any(|k| k.is_none()) {
+        return None;
+    }
+    let keywords = keywords.map(|k| copy *k.get_ref());
+    match keywords {
+        [~"auto"] => return Some(CSSPageSizeAuto),
+        [~"portrait"] => return Some(CSSPageSizePortrait),
+        [~"landscape"] => return Some(CSSPageSizeLandscape),
+        _ => ()
+    }
+
+    let mut size = None;
+    let mut landscape = None;
+    for keywords.each |k| {
+        match *k {
+            ~"portrait" | ~"landscape" if landscape.is_none() => landscape = Some(*k == ~"landscape"),
+            _ if size.is_none() => {
+                match named_page_size(*k) {
+                    Some(s) => size = Some(s),
+                    None => return None
+                }
+            }
+            _ => return None
+        }
+    }
+    match size {
+        Some((width, height)) => {
+            // Named sizes are portrait
+            if landscape == Some(true) {
+                Some(CSSPageSizeLength(height, width))
+            } else {
+                Some(CSSPageSizeLength(width, height))
+            }
+        }
+        None => None
+    }
+}
+
+/** The width and height of a named paper size, CSS Paged Media Level 3 section 7.1 */
+fn named_page_size(name: &str) -> Option<(Length, Length)> {
+    let mm = |width: float, height: float| Some((Pt(width * 72.0 / 25.4), Pt(height * 72.0 / 25.4)));
+    match name {
+        "a5" => mm(148.0, 210.0),
+        "a4" => mm(210.0, 297.0),
+        "a3" => mm(297.0, 420.0),
+        "b5" => mm(176.0, 250.0),
+        "b4" => mm(250.0, 353.0),
+        "letter" => Some((Pt(8.5 * 72.0), Pt(11.0 * 72.0))),
+        "legal" => Some((Pt(8.5 * 72.0), Pt(14.0 * 72.0))),
+        "ledger" => Some((Pt(11.0 * 72.0), Pt(17.0 * 72.0))),
+        _ => None
+    }
+}
+
+fn parse_background_color(c: &[Token]) -> Option<CSSBackgroundColor> {
+    match keyword(c) {
+        Some(~"transparent") => Some(CSSBackgroundColorTransparent),
+        _ => parse_color(c).map(|c| CSSBackgroundColorColor(*c))
+    }
+}
+
+fn parse_background_image(c: &[Token], base: &Url) -> Option<CSSBackgroundImage> {
+    match c {
+        [Uri(ref url)] => {
+            let resolved = resolve_relative(base.to_str(), *url).get_or_default(copy *url);
+            match url_from_str(resolved) {
+                Ok(url) => Some(CSSBackgroundUri(url)),
+                Err(_) => None
+            }
+        }
+        _ => match keyword(c) {
+            Some(~"none") => Some(CSSBackgroundImageNone),
+            _ => None
+        }
+    }
+}
+
+fn parse_background_repeat(c: &[Token]) -> Option<CSSBackgroundRepeat> {
+    match keyword(c) {
+        Some(~"repeat") => Some(CSSBackgroundRepeatRepeat),
+        Some(~"repeat-x") => Some(CSSBackgroundRepeatRepeatX),
+        Some(~"repeat-y") => Some(CSSBackgroundRepeatRepeatY),
+        Some(~"no-repeat") => Some(CSSBackgroundRepeatNoRepeat),
+        _ => None
+    }
+}
+
+fn parse_background_attachment(c: &[Token]) -> Option<CSSBackgroundAttachment> {
+    match keyword(c) {
+        Some(~"scroll") => Some(CSSBackgroundAttachmentScroll),
+        Some(~"fixed") => Some(CSSBackgroundAttachmentFixed),
+        _ => None
+    }
+}
+
+fn parse_position_component(c: &[Token]) -> Option<CSSBackgroundPosition> {
+    match keyword(c) {
+        Some(~"left") => Some(CSSBackgroundPositionLeft),
+        Some(~"center") => Some(CSSBackgroundPositionCenter),
+        Some(~"right") => Some(CSSBackgroundPositionRight),
+        Some(~"top") => Some(CSSBackgroundPositionTop),
+        Some(~"bottom") => Some(CSSBackgroundPositionBottom),
+        Some(_) => None,
+        None => match parse_length(c) {
+            Some(l) => Some(CSSBackgroundPositionLength(l)),
+            None => parse_percentage(c).map(|p| CSSBackgroundPositionPercentage(*p))
+        }
+    }
+}
+
+/** One or two position components, put in horizontal, vertical order */
+fn parse_background_position(cs: &[~[Token]])
+                             -> Option<(CSSBackgroundPosition, CSSBackgroundPosition)> {
+    fn is_vertical(p: &CSSBackgroundPosition) -> bool {
+        match *p {
+            CSSBackgroundPositionTop | CSSBackgroundPositionBottom => true,
+            _ => false
+        }
+    }
+    fn is_horizontal(p: &CSSBackgroundPosition) -> bool {
+        match *p {
+            CSSBackgroundPositionLeft | CSSBackgroundPositionRight => true,
+            _ => false
+        }
+    }
+    fn is_keyword(p: &CSSBackgroundPosition) -> bool {
+        match *p {
+            CSSBackgroundPositionLength(_) | CSSBackgroundPositionPercentage(_) => false,
+            _ => true
+        }
+    }
+
+    match cs.len() {
+        1 => parse_position_component(cs[0]).map(|p| {
+            if is_vertical(p) {
+                (CSSBackgroundPositionCenter, copy *p)
+            } else {
+                (copy *p, CSSBackgroundPositionCenter)
+            }
+        }),
+        2 => match (parse_position_component(cs[0]), parse_position_component(cs[1])) {
+            (Some(first), Some(second)) => {
+                // Keywords may come in either order; other values are horizontal first
+                if is_keyword(&first) && is_keyword(&second) &&
+                   (is_vertical(&first) || is_horizontal(&second)) {
+                    if is_vertical(&second) || is_horizontal(&first) {
+                        None
+                    } else {
+                        Some((second, first))
+                    }
+                } else if is_vertical(&first) || is_horizontal(&second) {
+                    None
+                } else {
+                    Some((first, second))
+                }
+            }
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/** A comma-separated list of quoted names, runs of identifiers and generic families */
+fn parse_font_family(cs: &[~[Token]]) -> Option<~[CSSFontFamily]> {
+    let mut families = ~[];
+    let mut name_parts: ~[~str] = ~[];
+    let mut i = 0;
+    loop {
+        let at_end = i == cs.len();
+        let is_comma = !at_end && cs[i] == ~[Delim(',')];
+        if at_end || is_comma {
+            match name_parts.len() {
+                0 => return None,
+                1 => {
+                    let generic = match name_parts[0].to_lower() {
+                        ~"serif" => Some(Serif),
+                        ~"sans-serif" => Some(SansSerif),
+                        ~"cursive" => Some(Cursive),
+                        ~"fantasy" => Some(Fantasy),
+                        ~"monospace" => Some(Monospace),
+                        _ => None
+                    };
+                    match generic {
+                        Some(generic) => families.push(CSSFontFamilyGenericFamily(generic)),
+                        None => families.push(CSSFontFamilyFamilyName(copy name_parts[0]))
+                    }
+                }
+                _ => families.push(CSSFontFamilyFamilyName(str::connect(name_parts, " ")))
+            }
+            name_parts = ~[];
+            if at_end {
+                break;
+            }
+        } else {
+            match cs[i] {
+                [QuotedString(ref name)] if name_parts.is_empty() => {
+                    // A quoted name must be followed by a comma or the end
+                    if i + 1 < cs.len() && cs[i + 1] != ~[Delim(',')] {
+                        return None;
+                    }
+                    families.push(CSSFontFamilyFamilyName(copy *name));
+                    if i + 1 == cs.len() {
+                        return Some(families);
+                    }
+                    i += 2;
+                    if i == cs.len() {
+                        return None;
+                    }
+                    loop;
+                }
+                [Ident(ref name)] => name_parts.push(copy *name),
+                _ => return None
+            }
+        }
+        i += 1;
+    }
+    Some(families)
+}
+
+fn parse_font_style(c: &[Token]) -> Option<CSSFontStyle> {
+    match keyword(c) {
+        Some(~"normal") => Some(CSSFontStyleNormal),
+        Some(~"italic") => Some(CSSFontStyleItalic),
+        Some(~"oblique") => Some(CSSFontStyleOblique),
+        _ => None
+    }
+}
+
+fn parse_font_weight(c: &[Token]) -> Option<CSSFontWeight> {
+    match c {
+        [Number(100.0)] => Some(CSSFontWeight100),
+        [Number(200.0)] => Some(CSSFontWeight200),
+        [Number(300.0)] => Some(CSSFontWeight300),
+        [Number(400.0)] => Some(CSSFontWeight400),
+        [Number(500.0)] => Some(CSSFontWeight500),
+        [Number(600.0)] => Some(CSSFontWeight600),
+        [Number(700.0)] => Some(CSSFontWeight700),
+        [Number(800.0)] => Some(CSSFontWeight800),
+        [Number(900.0)] => Some(CSSFontWeight900),
+        _ => match keyword(c) {
+            Some(~"normal") => Some(CSSFontWeightNormal),
+            Some(~"bold") => Some(CSSFontWeightBold),
+            Some(~"bolder") => Some(CSSFontWeightBolder),
+            Some(~"lighter") => Some(CSSFontWeightLighter),
+            _ => None
+        }
+    }
+}
+
+fn parse_font_size(c: &[Token]) -> Option<CSSFontSize> {
+    match keyword(c) {
+        Some(~"xx-small") => Some(CSSFontSizeAbsoluteSize(XXSmall)),
+        Some(~"x-small") => Some(CSSFontSizeAbsoluteSize(XSmall)),
+        Some(~"small") => Some(CSSFontSizeAbsoluteSize(Small)),
+        Some(~"medium") => Some(CSSFontSizeAbsoluteSize(Medium)),
+        Some(~"large") => Some(CSSFontSizeAbsoluteSize(Large)),
+        Some(~"x-large") => Some(CSSFontSizeAbsoluteSize(XLarge)),
+        Some(~"xx-large") => Some(CSSFontSizeAbsoluteSize(XXLarge)),
+        Some(~"larger") => Some(CSSFontSizeRelativeSize(Larger)),
+        Some(~"smaller") => Some(CSSFontSizeRelativeSize(Smaller)),
+        Some(_) => None,
+        None => match parse_non_negative_length(c) {
+            Some(l) => Some(CSSFontSizeLength(l)),
+            None => parse_non_negative_percentage(c).map(|p| CSSFontSizePercentage(*p))
+        }
+    }
+}
+
+fn parse_text_align(c: &[Token]) -> Option<CSSTextAlign> {
+    match keyword(c) {
+        Some(~"left") => Some(CSSTextAlignLeft),
+        Some(~"right") => Some(CSSTextAlignRight),
+        Some(~"center") => Some(CSSTextAlignCenter),
+        Some(~"justify") => Some(CSSTextAlignJustify),
+        _ => None
+    }
+}
+
+fn parse_text_decoration(cs: &[~[Token]]) -> Option<~[CSSTextDecoration]> {
+    if cs.len() == 1 && keyword(cs[0]) == Some(~"none") {
+        return Some(~[CSSTextDecorationNone]);
+    }
+    let mut decorations = ~[];
+    for cs.each |c| {
+        let decoration = match keyword(*c) {
+            Some(~"underline") => CSSTextDecorationUnderline,
+            Some(~"overline") => CSSTextDecorationOverline,
+            Some(~"line-through") => CSSTextDecorationLineThrough,
+            Some(~"blink") => CSSTextDecorationBlink,
+            _ => return None
+        };
+        if decorations.contains(&decoration) {
+            return None;
+        }
+        decorations.push(decoration);
+    }
+    if decorations.is_empty() { None } else { Some(decorations) }
+}
+
+fn parse_text_transform(c: &[Token]) -> Option<CSSTextTransform> {
+    match keyword(c) {
+        Some(~"capitalize") => Some(CSSTextTransformCapitalize),
+        Some(~"uppercase") => Some(CSSTextTransformUppercase),
+        Some(~"lowercase") => Some(CSSTextTransformLowercase),
+        Some(~"none") => Some(CSSTextTransformNone),
+        _ => None
+    }
+}
+
+/** Expand a shorthand of one to four values for the top, right, bottom and left sides */
+fn expand_box<T: Copy>(cs: &[~[Token]], inherit: bool, parse: &fn(&[Token]) -> Option<T>,
+                       top: &fn(CSSValue<T>) -> PropertyValue,
+                       right: &fn(CSSValue<T>) -> PropertyValue,
+                       bottom: &fn(CSSValue<T>) -> PropertyValue,
+                       left: &fn(CSSValue<T>) -> PropertyValue) -> ~[PropertyValue] {
+    if inherit {
+        return ~[top(Inherit), right(Inherit), bottom(Inherit), left(Inherit)];
+    }
+    if cs.len() < 1 || cs.len() > 4 {
+        return ~[];
+    }
+    let mut sides = ~[];
+    for cs.each |c| {
+        match parse(*c) {
+            Some(v) => sides.push(v),
+            None => return ~[]
+        }
+    }
+    let (t, r, b, l) = match sides.len() {
+        1 => (copy sides[0], copy sides[0], copy sides[0], copy sides[0]),
+        2 => (copy sides[0], copy sides[1], copy sides[0], copy sides[1]),
+        3 => (copy sides[0], copy sides[1], copy sides[2], copy sides[1]),
+        _ => (copy sides[0], copy sides[1], copy sides[2], copy sides[3])
+    };
+    ~[top(Specified(t)), right(Specified(r)), bottom(Specified(b)), left(Specified(l))]
+}
+
+/**
+Expand `border-top` and friends: a width, style and color in any order.
+Omitted parts are reset to their initial values, except the color, whose
+initial value is the element's own color and so is left out.
+*/
+fn expand_border_side(cs: &[~[Token]], inherit: bool,
+                      width: &fn(CSSValue<CSSBorderWidth>) -> PropertyValue,
+                      style: &fn(CSSValue<CSSBorderStyle>) -> PropertyValue,
+                      color: &fn(CSSValue<CSSBorderColor>) -> PropertyValue) -> ~[PropertyValue] {
+    if inherit {
+        return ~[width(Inherit), style(Inherit), color(Inherit)];
+    }
+    if cs.is_empty() || cs.len() > 3 {
+        return ~[];
+    }
+    let mut found_width = None;
+    let mut found_style = None;
+    let mut found_color = None;
+    for cs.each |c| {
+        if found_width.is_none() && parse_border_width(*c).is_some() {
+            found_width = parse_border_width(*c);
+        } else if found_style.is_none() && parse_border_style(*c).is_some() {
+            found_style = parse_border_style(*c);
+        } else if found_color.is_none() && parse_border_color(*c).is_some() {
+            found_color = parse_border_color(*c);
+        } else {
+            return ~[];
+        }
+    }
+    let mut values = ~[
+        width(Specified(found_width.get_or_default(CSSBorderWidthMedium))),
+        style(Specified(found_style.get_or_default(CSSBorderStyleNone)))
+    ];
+    match found_color {
+        Some(c) => values.push(color(Specified(c))),
+        None => ()
+    }
+    values
+}
+
+/** Expand `background`; omitted parts are reset to their initial values */
+fn expand_background(cs: &[~[Token]], inherit: bool, base: &Url) -> ~[PropertyValue] {
+    if inherit {
+        return ~[BackgroundColorValue(Inherit), BackgroundImageValue(Inherit),
+                 BackgroundRepeatValue(Inherit), BackgroundAttachmentValue(Inherit),
+                 BackgroundPositionValue(Inherit)];
+    }
+    if cs.is_empty() {
+        return ~[];
+    }
+    let mut color = None;
+    let mut image = None;
+    let mut repeat = None;
+    let mut attachment = None;
+    let mut position = None;
+    let mut i = 0;
+    while i < cs.len() {
+        let c: &[Token] = cs[i];
+        if color.is_none() && parse_background_color(c).is_some() {
+            color = parse_background_color(c);
+        } else if image.is_none() && parse_background_image(c, base).is_some() {
+            image = parse_background_image(c, base);
+        } else if repeat.is_none() && parse_background_repeat(c).is_some() {
+            repeat = parse_background_repeat(c);
+        } else if attachment.is_none() && parse_background_attachment(c).is_some() {
+            attachment = parse_background_attachment(c);
+        } else if position.is_none() && parse_position_component(c).is_some() {
+            // The two position values must be adjacent
+            let pair = if i + 1 < cs.len() && parse_position_component(cs[i + 1]).is_some() {
+                parse_background_position(cs.slice(i, i + 2))
+            } else {
+                None
+            };
+            if pair.is_some() {
+                position = pair;
+                i += 1;
+            } else {
+                position = parse_background_position(cs.slice(i, i + 1));
+            }
+        } else {
+            return ~[];
+        }
+        i += 1;
+    }
+    let initial_position = (CSSBackgroundPositionPercentage(0.0),
+                            CSSBackgroundPositionPercentage(0.0));
+    ~[
+        BackgroundColorValue(Specified(color.get_or_default(CSSBackgroundColorTransparent))),
+        BackgroundImageValue(Specified(image.get_or_default(CSSBackgroundImageNone))),
+        BackgroundRepeatValue(Specified(repeat.get_or_default(CSSBackgroundRepeatRepeat))),
+        BackgroundAttachmentValue(Specified(
+            attachment.get_or_default(CSSBackgroundAttachmentScroll))),
+        BackgroundPositionValue(Specified(position.get_or_default(initial_position)))
+    ]
+}
+
+/**
+Expand `font`: an optional style, variant and weight in any order, then a
+size, an optional `/line-height` and the family list. The system font
+keywords are kept unparsed.
+*/
+fn expand_font(cs: &[~[Token]], inherit: bool) -> ~[PropertyValue] {
+    if inherit {
+        return ~[FontStyleValue(Inherit), UnparsedValue(~"font-variant", ~"inherit"),
+                 FontWeightValue(Inherit), FontSizeValue(Inherit), LineHeightValue(Inherit),
+                 FontFamilyValue(Inherit)];
+    }
+    if cs.len() == 1 {
+        match keyword(cs[0]) {
+            Some(~"caption") | Some(~"icon") | Some(~"menu") | Some(~"message-box") |
+            Some(~"small-caption") | Some(~"status-bar") => {
+                return ~[UnparsedValue(~"font", keyword(cs[0]).get())];
+            }
+            _ => ()
+        }
+    }
+
+    let mut style = None;
+    let mut variant = None;
+    let mut weight = None;
+    let mut i = 0;
+    // `normal` may stand for any of the three
+    while i < cs.len() {
+        let c: &[Token] = cs[i];
+        if keyword(c) == Some(~"normal") {
+            i += 1;
+        } else if style.is_none() && parse_font_style(c).is_some() {
+            style = parse_font_style(c);
+            i += 1;
+        } else if variant.is_none() && keyword(c) == Some(~"small-caps") {
+            variant = Some(~"small-caps");
+            i += 1;
+        } else if weight.is_none() && parse_font_weight(c).is_some() {
+            weight = parse_font_weight(c);
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    if i >= cs.len() {
+        return ~[];
+    }
+    let size = match parse_font_size(cs[i]) {
+        Some(size) => size,
+        None => return ~[]
+    };
+    i += 1;
+    let mut line_height = CSSLineHeightNormal;
+    if i < cs.len() && cs[i] == ~[Delim('/')] {
+        if i + 1 >= cs.len() {
+            return ~[];
+        }
+        match parse_line_height(cs[i + 1]) {
+            Some(lh) => line_height = lh,
+            None => return ~[]
+        }
+        i += 2;
+    }
+    let family = match parse_font_family(cs.slice(i, cs.len())) {
+        Some(family) => family,
+        None => return ~[]
+    };
+    ~[
+        FontStyleValue(Specified(style.get_or_default(CSSFontStyleNormal))),
+        UnparsedValue(~"font-variant", variant.get_or_default(~"normal")),
+        FontWeightValue(Specified(weight.get_or_default(CSSFontWeightNormal))),
+        FontSizeValue(Specified(size)),
+        LineHeightValue(Specified(line_height)),
+        FontFamilyValue(Specified(family))
+    ]
+}
@@ -0,0 +1,703 @@
+/*!
+The rules of a parsed stylesheet, for inspecting what a sheet contains.
+
+These are built by the syntax layer alongside libcss, which keeps its own
+rule tree private. Rules libcss would drop, such as style rules with an
+invalid selector, are left out here too.
+
+The two parses don't always agree on declarations, as not every property
+has a typed value here:
+
+* Properties without one are kept as `UnparsedValue` text, whatever the
+  text is. libcss drops those with invalid values, so `z-index: red` is a
+  declaration here but has no effect on selection.
+* `top` percentages are kept as text, as `CSSTop` can't hold them.
+* `currentColor` and the system colors are kept as text, as they are only
+  known at selection time.
+
+Typed values that don't fit their property's grammar are dropped by both.
+*/
+
+use std::net::url::Url;
+use url_from_str = std::net::url::from_str;
+use types::{MediaList, PseudoElement, PseudoElementFirstLine};
+use media_queries::parse_media_query;
+use types::{PseudoElementFirstLetter, PseudoElementBefore, PseudoElementAfter};
+use properties::{PropertyDeclaration, parse_declaration, parse_font_face};
+use values::{CSSFontWeight, CSSFontStyle};
+use parser::resolve_relative;
+use syntax::{RawRule, SourceToken, Token, Ident, QuotedString, Hash, Number, Dimension, Uri};
+use syntax::{Function, Colon, LeftBracket, RightBracket, RightParen, Whitespace, Delim};
+use syntax::{Includes, DashMatch, PrefixMatch, SuffixMatch, SubstringMatch};
+
+/** A statement of a stylesheet */
+#[deriving_eq]
+pub enum Rule {
+    RuleStyle(StyleRule),
+    RuleMedia(MediaRule),
+    RuleImport(ImportRule),
+    RuleFontFace(FontFaceRule),
+    RulePage(PageRule)
+}
+
+/** A selector list and the declarations applied to the elements it matches */
+#[deriving_eq]
+pub struct StyleRule {
+    selectors: ~[Selector],
+    declarations: ~[PropertyDeclaration]
+}
+
+/** An `@media` block */
+#[deriving_eq]
+pub struct MediaRule {
+    media: MediaList,
+    rules: ~[Rule]
+}
+
+/** An `@import` rule. The imported sheet itself is in `Stylesheet::imports`. */
+#[deriving_eq]
+pub struct ImportRule {
+    /// The url, resolved against the importing sheet's
+    url: Url,
+    media: MediaList
+}
+
+/**
+An `@font-face` block, declaring a web font. Descriptors other than these
+are not kept, and a block without a `font-family` or `src` is dropped.
+*/
+#[deriving_eq]
+pub struct FontFaceRule {
+    family: ~str,
+    /// Where the font can be found, in order of preference
+    sources: ~[FontFaceSource],
+    weight: CSSFontWeight,
+    style: CSSFontStyle,
+    /// The ranges of code points the font covers, first and last inclusive
+    unicode_range: ~[(u32, u32)]
+}
+
+#[deriving_eq]
+pub enum FontFaceSource {
+    /// A resolved url and the `format()` hints given for it, in lower case
+    FontFaceSourceUrl(Url, ~[~str]),
+    /// `local()`, the name of a font installed on the system
+    FontFaceSourceLocal(~str)
+}
+
+/** An `@page` block, setting the margins and `size` of page boxes */
+#[deriving_eq]
+pub struct PageRule {
+    /// The pages the rule applies to, or `None` for all of them
+    selector: Option<PageSelector>,
+    declarations: ~[PropertyDeclaration]
+}
+
+/** The page pseudo-classes, CSS 2.1 section 13.2.2 */
+#[deriving_eq]
+pub enum PageSelector {
+    PageFirst,
+    PageLeft,
+    PageRight
+}
+
+pub impl PageSelector {
+    /** As CSS Paged Media Level 3 has it, `:first` outweighs `:left` and `:right` */
+    fn specificity(&self) -> uint {
+        match *self {
+            PageFirst => 2,
+            PageLeft | PageRight => 1
+        }
+    }
+}
+
+/**
+A complex selector: compound selectors joined by combinators, e.g.
+`ul > li.item:first-child`
+*/
+#[deriving_eq]
+pub struct Selector {
+    /// The leftmost compound selector
+    first: CompoundSelector,
+    /// The compound selectors after it, each with the combinator before it
+    rest: ~[(Combinator, CompoundSelector)],
+    /// The pseudo-element at the end of the selector, if any
+    pseudo_element: Option<PseudoElement>
+}
+
+/** A run of simple selectors with no combinators between them */
+#[deriving_eq]
+pub struct CompoundSelector {
+    /// The element name in lower case, or `None` for the universal selector
+    element: Option<~str>,
+    conditions: ~[SimpleSelector]
+}
+
+#[deriving_eq]
+pub enum Combinator {
+    DescendantCombinator,
+    ChildCombinator,
+    /// `+`
+    AdjacentSiblingCombinator,
+    /// `~`
+    GeneralSiblingCombinator
+}
+
+#[deriving_eq]
+pub enum SimpleSelector {
+    IdSelector(~str),
+    ClassSelector(~str),
+    AttributeSelector(~str, AttributeMatch),
+    PseudoClassSelector(PseudoClass)
+}
+
+/** How an attribute selector tests the attribute's value */
+#[deriving_eq]
+pub enum AttributeMatch {
+    /// `[attr]`
+    AttributeExists,
+    /// `[attr=value]`
+    AttributeEqual(~str),
+    /// `[attr~=value]`, a whitespace-separated word
+    AttributeIncludes(~str),
+    /// `[attr|=value]`, the value or the value followed by `-`
+    AttributeDashMatch(~str),
+    /// `[attr^=value]`
+    AttributePrefix(~str),
+    /// `[attr$=value]`
+    AttributeSuffix(~str),
+    /// `[attr*=value]`
+    AttributeSubstring(~str)
+}
+
+#[deriving_eq]
+pub enum PseudoClass {
+    PseudoLink,
+    PseudoVisited,
+    PseudoHover,
+    PseudoActive,
+    PseudoFocus,
+    PseudoFirstChild,
+    PseudoLastChild,
+    PseudoOnlyChild,
+    PseudoFirstOfType,
+    PseudoLastOfType,
+    PseudoOnlyOfType,
+    PseudoRoot,
+    PseudoEmpty,
+    PseudoTarget,
+    PseudoEnabled,
+    PseudoDisabled,
+    PseudoChecked,
+    PseudoLang(~str),
+    /// `:nth-child(an+b)`, as `(a, b)`
+    PseudoNthChild(int, int),
+    PseudoNthLastChild(int, int),
+    PseudoNthOfType(int, int),
+    PseudoNthLastOfType(int, int),
+    /// `:not()`, whose argument holds a single simple selector
+    PseudoNot(~CompoundSelector)
+}
+
+impl Selector {
+    /**
+    The specificity of the selector, CSS 2.1 section 6.4.3: the number of
+    ID selectors, of other attribute and pseudo-class selectors, and of
+    element names and pseudo-elements
+    */
+    fn specificity(&self) -> (uint, uint, uint) {
+        let mut specificity = self.first.specificity();
+        for self.rest.each |&(_, ref compound)| {
+            specificity = add_specificity(specificity, compound.specificity());
+        }
+        if self.pseudo_element.is_some() {
+            specificity = add_specificity(specificity, (0, 0, 1));
+        }
+        specificity
+    }
+}
+
+impl CompoundSelector {
+    fn specificity(&self) -> (uint, uint, uint) {
+        let mut specificity = if self.element.is_some() { (0, 0, 1) } else { (0, 0, 0) };
+        for self.conditions.each |condition| {
+            let s = match *condition {
+                IdSelector(_) => (1, 0, 0),
+                PseudoClassSelector(PseudoNot(ref negated)) => negated.specificity(),
+                _ => (0, 1, 0)
+            };
+            specificity = add_specificity(specificity, s);
+        }
+        specificity
+    }
+}
+
+fn add_specificity(a: (uint, uint, uint), b: (uint, uint, uint)) -> (uint, uint, uint) {
+    let (a1, a2, a3) = a;
+    let (b1, b2, b3) = b;
+    (a1 + b1, a2 + b2, a3 + b3)
+}
+
+/** The `@font-face` rules among `rules`, including those inside `@media` blocks */
+pub fn font_face_rules(rules: &[Rule]) -> ~[FontFaceRule] {
+    let mut font_faces = ~[];
+    for rules.each |rule| {
+        match *rule {
+            RuleFontFace(ref font_face) => font_faces.push(copy *font_face),
+            RuleMedia(ref media_rule) => font_faces.push_all(font_face_rules(media_rule.rules)),
+            _ => ()
+        }
+    }
+    font_faces
+}
+
+/** Build the rule tree from the statements found by the syntax layer */
+pub fn rules_from_raw(raw_rules: &[RawRule], base: &Url) -> ~[Rule] {
+    let mut rules = ~[];
+    for raw_rules.each |raw| {
+        match rule_from_raw(raw, base) {
+            Some(rule) => rules.push(rule),
+            None => ()
+        }
+    }
+    rules
+}
+
+/** Build a single rule, or `None` if it is invalid */
+pub fn rule_from_raw(raw: &RawRule, base: &Url) -> Option<Rule> {
+    let declarations = || {
+        let mut declarations = ~[];
+        for raw.declarations.each |declaration| {
+            declarations.push_all(parse_declaration(declaration, base));
+        }
+        declarations
+    };
+    let prelude = tokens(raw.prelude);
+
+    match raw.at_keyword {
+        None => {
+            do parse_selector_list(prelude).map |selectors| {
+                RuleStyle(StyleRule {
+                    selectors: copy *selectors,
+                    declarations: declarations()
+                })
+            }
+        }
+        Some(~"media") => {
+            Some(RuleMedia(MediaRule {
+                media: parse_media_list(prelude),
+                rules: rules_from_raw(raw.rules, base)
+            }))
+        }
+        Some(~"import") => {
+            let (href, media) = match prelude {
+                [Uri(ref href), ..media] | [QuotedString(ref href), ..media] => {
+                    (copy *href, parse_media_list(media))
+                }
+                _ => return None
+            };
+            let resolved = resolve_relative(base.to_str(), href).get_or_default(href);
+            match url_from_str(resolved) {
+                Ok(url) => Some(RuleImport(ImportRule { url: url, media: media })),
+                Err(_) => None
+            }
+        }
+        Some(~"font-face") => {
+            if !prelude.is_empty() {
+                return None;
+            }
+            parse_font_face(raw.declarations, base).map(|rule| RuleFontFace(copy *rule))
+        }
+        Some(~"page") => {
+            let selector = match prelude {
+                [] => None,
+                [Colon, Ident(ref name)] => {
+                    match name.to_lower() {
+                        ~"first" => Some(PageFirst),
+                        ~"left" => Some(PageLeft),
+                        ~"right" => Some(PageRight),
+                        _ => return None
+                    }
+                }
+                _ => return None
+            };
+            Some(RulePage(PageRule {
+                selector: selector,
+                declarations: declarations()
+            }))
+        }
+        // @charset only affects decoding
+        Some(_) => None
+    }
+}
+
+fn token_at(tokens: &[Token], pos: uint) -> Option<Token> {
+    if pos < tokens.len() { Some(copy tokens[pos]) } else { None }
+}
+
+fn tokens(source: &[SourceToken]) -> ~[Token] {
+    source.map(|t| copy t.token)
+}
+
+/** Split tokens at top-level commas, trimming whitespace from each part */
+fn split_commas(tokens: &[Token]) -> ~[~[Token]] {
+    let mut parts = ~[];
+    let mut part = ~[];
+    for tokens.each |token| {
+        if *token == Delim(',') {
+            parts.push(trim(part));
+            part = ~[];
+        } else {
+            part.push(copy *token);
+        }
+    }
+    parts.push(trim(part));
+    parts
+}
+
+fn trim(tokens: &[Token]) -> ~[Token] {
+    let mut start = 0;
+    let mut end = tokens.len();
+    while start < end && tokens[start] == Whitespace {
+        start += 1;
+    }
+    while end > start && tokens[end - 1] == Whitespace {
+        end -= 1;
+    }
+    tokens.slice(start, end).to_owned()
+}
+
+/**
+A comma-separated list of media queries. Queries that are invalid or name an
+unknown media type are skipped, since they never match.
+*/
+pub fn parse_media_list(tokens: &[Token]) -> MediaList {
+    let tokens = trim(tokens);
+    if tokens.is_empty() {
+        return MediaList::all();
+    }
+    let mut queries = ~[];
+    for split_commas(tokens).each |part| {
+        match parse_media_query(*part) {
+            Some(query) => queries.push(query),
+            None => ()
+        }
+    }
+    MediaList::from_queries(queries)
+}
+
+/** A comma-separated list of selectors. If any is invalid the whole list is. */
+pub fn parse_selector_list(tokens: &[Token]) -> Option<~[Selector]> {
+    let mut selectors = ~[];
+    for split_commas(tokens).each |part| {
+        match parse_selector(*part) {
+            Some(selector) => selectors.push(selector),
+            None => return None
+        }
+    }
+    Some(selectors)
+}
+
+fn parse_selector(tokens: &[Token]) -> Option<Selector> {
+    let mut pos = 0;
+    let (first, mut pseudo_element) = match parse_compound(tokens, &mut pos) {
+        Some(compound) => compound,
+        None => return None
+    };
+    let mut rest = ~[];
+
+    while pos < tokens.len() {
+        // A pseudo-element ends the selector
+        if pseudo_element.is_some() {
+            return None;
+        }
+        let mut whitespace = false;
+        while pos < tokens.len() && tokens[pos] == Whitespace {
+            pos += 1;
+            whitespace = true;
+        }
+        if pos == tokens.len() {
+            break;
+        }
+        let combinator = match tokens[pos] {
+            Delim('>') => ChildCombinator,
+            Delim('+') => AdjacentSiblingCombinator,
+            Delim('~') => GeneralSiblingCombinator,
+            _ if whitespace => DescendantCombinator,
+            _ => return None
+        };
+        if combinator != DescendantCombinator {
+            pos += 1;
+            while pos < tokens.len() && tokens[pos] == Whitespace {
+                pos += 1;
+            }
+        }
+        match parse_compound(tokens, &mut pos) {
+            Some((compound, pseudo)) => {
+                rest.push((combinator, compound));
+                pseudo_element = pseudo;
+            }
+            None => return None
+        }
+    }
+
+    Some(Selector {
+        first: first,
+        rest: rest,
+        pseudo_element: pseudo_element
+    })
+}
+
+fn parse_compound(tokens: &[Token], pos: &mut uint)
+                  -> Option<(CompoundSelector, Option<PseudoElement>)> {
+    let start = *pos;
+    let element = match token_at(tokens, *pos) {
+        Some(Ident(name)) => {
+            *pos += 1;
+            Some(name.to_lower())
+        }
+        Some(Delim('*')) => {
+            *pos += 1;
+            None
+        }
+        _ => None
+    };
+
+    let mut conditions = ~[];
+    let mut pseudo_element = None;
+    while *pos < tokens.len() && pseudo_element.is_none() {
+        match tokens[*pos] {
+            Hash(ref id) => {
+                conditions.push(IdSelector(copy *id));
+                *pos += 1;
+            }
+            Delim('.') => {
+                match token_at(tokens, *pos + 1) {
+                    Some(Ident(class)) => conditions.push(ClassSelector(class)),
+                    _ => return None
+                }
+                *pos += 2;
+            }
+            LeftBracket => {
+                match parse_attribute(tokens, pos) {
+                    Some(condition) => conditions.push(condition),
+                    None => return None
+                }
+            }
+            Colon => {
+                *pos += 1;
+                let double_colon = token_at(tokens, *pos) == Some(Colon);
+                if double_colon {
+                    *pos += 1;
+                }
+                let name = match token_at(tokens, *pos) {
+                    Some(Ident(name)) => Some(name.to_lower()),
+                    _ => None
+                };
+                match name.chain(|name| parse_pseudo_element(name)) {
+                    Some(pseudo) => {
+                        *pos += 1;
+                        pseudo_element = Some(pseudo);
+                        loop;
+                    }
+                    None if double_colon => return None,
+                    None => ()
+                }
+                match parse_pseudo_class(tokens, pos) {
+                    Some(pseudo) => conditions.push(PseudoClassSelector(pseudo)),
+                    None => return None
+                }
+            }
+            _ => break
+        }
+    }
+
+    if *pos == start {
+        return None;
+    }
+    Some((CompoundSelector {
+        element: element,
+        conditions: conditions
+    }, pseudo_element))
+}
+
+fn parse_pseudo_element(name: ~str) -> Option<PseudoElement> {
+    match name {
+        ~"first-line" => Some(PseudoElementFirstLine),
+        ~"first-letter" => Some(PseudoElementFirstLetter),
+        ~"before" => Some(PseudoElementBefore),
+        ~"after" => Some(PseudoElementAfter),
+        _ => None
+    }
+}
+
+/** An attribute selector, starting at the `[` */
+fn parse_attribute(tokens: &[Token], pos: &mut uint) -> Option<SimpleSelector> {
+    let mut end = *pos;
+    while end < tokens.len() && tokens[end] != RightBracket {
+        end += 1;
+    }
+    if end == tokens.len() {
+        return None;
+    }
+    let inner = trim(tokens.slice(*pos + 1, end));
+    *pos = end + 1;
+
+    let inner = inner.filtered(|t| *t != Whitespace);
+    let name = match token_at(inner, 0) {
+        Some(Ident(name)) => name,
+        _ => return None
+    };
+    if inner.len() == 1 {
+        return Some(AttributeSelector(name, AttributeExists));
+    }
+    if inner.len() != 3 {
+        return None;
+    }
+    let value = match inner[2] {
+        Ident(ref value) | QuotedString(ref value) => copy *value,
+        _ => return None
+    };
+    let test = match inner[1] {
+        Delim('=') => AttributeEqual(value),
+        Includes => AttributeIncludes(value),
+        DashMatch => AttributeDashMatch(value),
+        PrefixMatch => AttributePrefix(value),
+        SuffixMatch => AttributeSuffix(value),
+        SubstringMatch => AttributeSubstring(value),
+        _ => return None
+    };
+    Some(AttributeSelector(name, test))
+}
+
+/** A pseudo-class, starting just after the `:` */
+fn parse_pseudo_class(tokens: &[Token], pos: &mut uint) -> Option<PseudoClass> {
+    match token_at(tokens, *pos) {
+        Some(Ident(name)) => {
+            *pos += 1;
+            match name.to_lower() {
+                ~"link" => Some(PseudoLink),
+                ~"visited" => Some(PseudoVisited),
+                ~"hover" => Some(PseudoHover),
+                ~"active" => Some(PseudoActive),
+                ~"focus" => Some(PseudoFocus),
+                ~"first-child" => Some(PseudoFirstChild),
+                ~"last-child" => Some(PseudoLastChild),
+                ~"only-child" => Some(PseudoOnlyChild),
+                ~"first-of-type" => Some(PseudoFirstOfType),
+                ~"last-of-type" => Some(PseudoLastOfType),
+                ~"only-of-type" => Some(PseudoOnlyOfType),
+                ~"root" => Some(PseudoRoot),
+                ~"empty" => Some(PseudoEmpty),
+                ~"target" => Some(PseudoTarget),
+                ~"enabled" => Some(PseudoEnabled),
+                ~"disabled" => Some(PseudoDisabled),
+                ~"checked" => Some(PseudoChecked),
+                _ => None
+            }
+        }
+        Some(Function(name)) => {
+            let mut end = *pos + 1;
+            while end < tokens.len() && tokens[end] != RightParen {
+                end += 1;
+            }
+            if end == tokens.len() {
+                return None;
+            }
+            let args = trim(tokens.slice(*pos + 1, end));
+            *pos = end + 1;
+            match name.to_lower() {
+                ~"lang" => match args {
+                    [Ident(ref lang)] => Some(PseudoLang(copy *lang)),
+                    _ => None
+                },
+                ~"nth-child" => parse_nth(args).map(|&(a, b)| PseudoNthChild(a, b)),
+                ~"nth-last-child" => parse_nth(args).map(|&(a, b)| PseudoNthLastChild(a, b)),
+                ~"nth-of-type" => parse_nth(args).map(|&(a, b)| PseudoNthOfType(a, b)),
+                ~"nth-last-of-type" => parse_nth(args).map(|&(a, b)| PseudoNthLastOfType(a, b)),
+                ~"not" => {
+                    let mut arg_pos = 0;
+                    match parse_compound(args, &mut arg_pos) {
+                        Some((negated, None)) if arg_pos == args.len() &&
+                            (negated.element.is_none() || negated.conditions.is_empty()) &&
+                            negated.conditions.len() <= 1 => {
+                            match negated.conditions {
+                                [PseudoClassSelector(PseudoNot(_))] => None,
+                                _ => Some(PseudoNot(~negated))
+                            }
+                        }
+                        _ => None
+                    }
+                }
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+/** The argument of `:nth-child()` and friends, `an+b`, `odd` or `even` */
+fn parse_nth(args: &[Token]) -> Option<(int, int)> {
+    // The tokenizer splits `an+b` in several ways, e.g. `2n+1` is a
+    // dimension and a signed number, so put the text back together first
+    let mut text = ~"";
+    for args.each |arg| {
+        match *arg {
+            Whitespace => (),
+            Ident(ref s) => str::push_str(&mut text, *s),
+            Dimension(value, ref unit) if value == (value as int) as float => {
+                str::push_str(&mut text, fmt!("%d", value as int));
+                str::push_str(&mut text, *unit);
+            }
+            Number(value) if value == (value as int) as float => {
+                let sign_written = text.ends_with("+") || text.ends_with("-");
+                if value >= 0.0 && !sign_written && !text.is_empty() {
+                    str::push_char(&mut text, '+');
+                }
+                if sign_written && value < 0.0 {
+                    return None;
+                }
+                str::push_str(&mut text, fmt!("%d", value as int));
+            }
+            Delim('+') => str::push_char(&mut text, '+'),
+            Delim('-') => str::push_char(&mut text, '-'),
+            _ => return None
+        }
+    }
+    let text = text.to_lower();
+
+    fn parse_int(s: &str) -> Option<int> {
+        if s.starts_with("+") {
+            int::from_str(s.slice(1, s.len()))
+        } else {
+            int::from_str(s)
+        }
+    }
+
+    match text {
+        ~"odd" => Some((2, 1)),
+        ~"even" => Some((2, 0)),
+        _ => match str::find_char(text, 'n') {
+            None => parse_int(text).map(|b| (0, *b)),
+            Some(n) => {
+                let a = match text.slice(0, n) {
+                    "" | "+" => Some(1),
+                    "-" => Some(-1),
+                    a => parse_int(a)
+                };
+                let b_text = text.slice(n + 1, text.len());
+                let b = if b_text.is_empty() {
+                    Some(0)
+                } else if b_text.starts_with("+") || b_text.starts_with("-") {
+                    parse_int(b_text)
+                } else {
+                    None
+                };
+                match (a, b) {
+                    (Some(a), Some(b)) => Some((a, b)),
+                    _ => None
+                }
+            }
+        }
+    }
+}
@@ -7,16 +7,65 @@ uses to query various DOM and UA properties.
 */
 
 use stylesheet::Stylesheet;
+use netsurfcss::stylesheet::CssStylesheet;
 use computed::ComputedStyle;
 use util::VoidPtrLike;
 use wapcaplet::LwcString;
 use lwcstr_from_rust_str = wapcaplet::from_rust_string;
 use n::u::{rust_str_to_net_qname, net_qname_to_rust_str};
-use types::StylesheetOrigin;
+use types::{StylesheetOrigin, OriginUA, MediaType, MediaScreen, MediaPrint, MediaList};
+use types::{PseudoElement, PseudoElementFirstLine, PseudoElementFirstLetter};
+use types::{PseudoElementBefore, PseudoElementAfter};
+use rules::{PageRule, RulePage};
+use page::{PageSide, PageStyle, cascade_page_rules};
+use media_queries::Device;
+use html::html_ua_stylesheet;
+use properties::{Property, PropertyColor, PropertyFontFamily, PropertyQuotes, PropertyValue};
+use properties::{ColorValue, QuotesValue};
+use properties::{FontFamilyValue, FontSizeValue, BackgroundColorValue, BorderTopColorValue};
+use properties::{BorderRightColorValue, BorderBottomColorValue, BorderLeftColorValue};
+use properties::{BorderTopStyleValue, BorderRightStyleValue, BorderBottomStyleValue};
+use properties::{BorderLeftStyleValue, BorderTopWidthValue, BorderRightWidthValue};
+use properties::{BorderBottomWidthValue, BorderLeftWidthValue, MarginTopValue, MarginRightValue};
+use properties::{MarginBottomValue, MarginLeftValue, PaddingTopValue, PaddingRightValue};
+use properties::{PaddingBottomValue, PaddingLeftValue, WidthValue, HeightValue, FloatValue};
+use properties::TextAlignValue;
+use values::{Specified, CSSColorColor, CSSBackgroundColorColor, CSSBorderColorColor};
+use values::{CSSFontFamilyFamilyName, CSSFontFamilyGenericFamily, CSSFontSizeAbsoluteSize};
+use values::{CSSFontSizeLength, CSSFontSizePercentage, CSSBorderWidthLength, CSSBorderStyle};
+use values::{CSSBorderStyleNone, CSSBorderStyleHidden, CSSBorderStyleDotted, CSSBorderStyleDashed};
+use values::{CSSBorderStyleSolid, CSSBorderStyleDouble, CSSBorderStyleGroove, CSSBorderStyleRidge};
+use values::{CSSBorderStyleInset, CSSBorderStyleOutset, CSSMarginLength, CSSPaddingLength};
+use values::{CSSWidthLength, CSSWidthPercentage, CSSHeightLength, CSSHeightPercentage};
+use values::{CSSFloatLeft, CSSFloatRight, CSSFloatNone, CSSTextAlignLeft, CSSTextAlignRight};
+use values::{CSSTextAlignCenter, CSSTextAlignJustify, CSSQuotesNone, CSSQuotesPairs};
+use units::{Length, Px, Em, Ex, Pt, XXSmall, XSmall, Small, Medium, Large, XLarge, XXLarge};
+use units::{Serif, SansSerif, Cursive, Fantasy, Monospace};
+use color::Color;
+use n::u::float_to_css_fixed;
+use parser::net_stylesheet_for_device;
 use n;
 
 pub struct SelectCtx {
-    inner: n::s::CssSelectCtx
+    inner: n::s::CssSelectCtx,
+    /// The sheets given to `append_sheet`, in order
+    sheets: ~[AppendedSheet],
+    /// What media feature expressions are evaluated against
+    device: Device
+}
+
+/** A sheet in a `SelectCtx`, which libcss sees as the sheet plus one per import */
+struct AppendedSheet {
+    origin: StylesheetOrigin,
+    /// The media given to `append_sheet_with_media`
+    media: MediaList,
+    /// The sheet itself, kept if it has media feature expressions so it can
+    /// be rebuilt when the device changes
+    sheet: Option<Stylesheet>,
+    /// How many libcss sheets the sheet and its imports became
+    net_sheets: uint,
+    /// The `@page` rules of the sheet and its imports that apply to print
+    page_rules: ~[PageRule]
 }
 
 /**
@@ -25,37 +74,288 @@ The SelectCtx, used for performing selector matching.
 The `SelectCtx` takes ownership of any number of `Stylesheet` objects,
 encapsulates the cascade. Individual node styles can be requested with
 the `select_style` method.
+
+Media queries are evaluated against the context's `Device`. libcss can't
+evaluate feature expressions, so sheets that have them are rebuilt for the
+device when they are appended and again by `set_device`.
 */
 pub impl SelectCtx {
+    /**
+    A context for an assumed 800 by 600 screen. Media feature expressions
+    such as `(max-width: 600px)` are evaluated against that size whatever
+    the real viewport is; give the real device to `new_with_device`, or to
+    `set_device` when it changes.
+    */
     static fn new() -> SelectCtx {
+        SelectCtx::new_with_device(Device::new(800.0, 600.0))
+    }
+
+    static fn new_with_device(device: Device) -> SelectCtx {
         SelectCtx {
-            inner: n::s::css_select_ctx_create()
+            inner: n::s::css_select_ctx_create(),
+            sheets: ~[],
+            device: device
         }
     }
 
+    /** A context with the default HTML sheet already appended as `OriginUA` */
+    static fn new_with_html_ua() -> SelectCtx {
+        let mut ctx = SelectCtx::new();
+        ctx.append_sheet(html_ua_stylesheet(), OriginUA);
+        ctx
+    }
+
     /**
     Add `Stylesheet`s to the selection context, where they will participate in the cascade
     during future selector matching
     */
     fn append_sheet(&mut self, sheet: Stylesheet, origin: StylesheetOrigin) {
-        let sheet = match sheet {
-            Stylesheet { inner: inner } => inner
+        self.append_sheet_with_media(sheet, origin, MediaList::all())
+    }
+
+    /**
+    Add a `Stylesheet` that applies only to `media`, e.g. one from
+    `<link media="print">`. The sheet's own media, from its
+    `StylesheetParams`, still applies as well.
+    */
+    fn append_sheet_with_media(&mut self, sheet: Stylesheet, origin: StylesheetOrigin,
+                               media: MediaList) {
+        let position = self.net_position(self.sheets.len());
+        let appended = self.insert_appended_sheet(sheet, origin, media, position);
+        self.sheets.push(appended);
+    }
+
+    /** The number of sheets appended, not counting imported sheets */
+    fn sheet_count(&self) -> uint {
+        self.sheets.len()
+    }
+
+    /**
+    Swap the sheet appended at `index` for a new version of it, e.g. a
+    clone of a sheet changed by `insert_rule` or `delete_rule`. It keeps
+    its origin, media and place in the cascade. The other sheets are left alone,
+    so this is much cheaper than building a new `SelectCtx`.
+    */
+    fn replace_sheet(&mut self, index: uint, sheet: Stylesheet) {
+        fail_unless!(index < self.sheets.len());
+        self.remove_net_sheets(index);
+        let position = self.net_position(index);
+        let origin = self.sheets[index].origin;
+        let media = copy self.sheets[index].media;
+        self.sheets[index] = self.insert_appended_sheet(sheet, origin, media, position);
+    }
+
+    fn device(&self) -> Device {
+        self.device
+    }
+
+    /**
+    The indices of the appended sheets that would apply differently on
+    `device`, e.g. after the viewport is resized. `Stylesheet::rules_changed_by_device`
+    says which of a sheet's rules are affected.
+    */
+    fn sheets_changed_by_device(&self, device: &Device) -> ~[uint] {
+        let mut changed = ~[];
+        for self.sheets.eachi |i, appended| {
+            let media_changed = appended.media.to_net(&self.device) != appended.media.to_net(device);
+            let sheet_changed = match appended.sheet {
+                Some(ref sheet) => sheet.changed_by_device(&self.device, device),
+                None => false
+            };
+            if media_changed || sheet_changed {
+                changed.push(i);
+            }
+        }
+        changed
+    }
+
+    /**
+    Evaluate media queries against `device` from now on, rebuilding the
+    sheets that apply differently on it. Returns the indices of those
+    sheets, as `sheets_changed_by_device` does.
+    */
+    fn set_device(&mut self, device: Device) -> ~[uint] {
+        let changed = self.sheets_changed_by_device(&device);
+        self.device = device;
+        for changed.each |&index| {
+            self.remove_net_sheets(index);
+            let position = self.net_position(index);
+            let origin = self.sheets[index].origin;
+            let media = self.sheets[index].media.to_net(&self.device);
+            let sheet = self.sheets[index].sheet.swap_unwrap();
+            self.sheets[index].net_sheets = self.insert_sheet_for_device(&sheet, origin, media,
+                                                                         position);
+            self.sheets[index].page_rules = self.print_page_rules(&sheet, media);
+            self.sheets[index].sheet = Some(sheet);
+        }
+        changed
+    }
+
+    /** Where the libcss sheets of the sheet appended at `index` start */
+    priv fn net_position(&self, index: uint) -> uint {
+        let mut position = 0;
+        for self.sheets.slice(0, index).each |sheet| {
+            position += sheet.net_sheets;
+        }
+        position
+    }
+
+    priv fn remove_net_sheets(&mut self, index: uint) {
+        let position = self.net_position(index);
+        for self.sheets[index].net_sheets.times {
+            self.inner.remove_sheet(position);
+        }
+    }
+
+    /**
+    Give libcss `sheet`, starting at `position` among its sheets. Sheets
+    whose media depend on the device are built for it and kept.
+    */
+    priv fn insert_appended_sheet(&mut self, sheet: Stylesheet, origin: StylesheetOrigin,
+                                  media: MediaList, position: uint) -> AppendedSheet {
+        let media_bits = media.to_net(&self.device);
+        let page_rules = self.print_page_rules(&sheet, media_bits);
+        if sheet.depends_on_device() || media.depends_on_device() {
+            let net_sheets = self.insert_sheet_for_device(&sheet, origin, media_bits, position);
+            AppendedSheet {
+                origin: origin,
+                media: media,
+                sheet: Some(sheet),
+                net_sheets: net_sheets,
+                page_rules: page_rules
+            }
+        } else {
+            let net_sheets = self.insert_sheet_and_imports(sheet, origin, media_bits, position);
+            AppendedSheet {
+                origin: origin,
+                media: media,
+                sheet: None,
+                net_sheets: net_sheets,
+                page_rules: page_rules
+            }
+        }
+    }
+
+    /**
+    Give libcss `sheet` and its imports, starting at `position` among its
+    sheets, and return how many sheets that was. Imported sheets cascade as
+    though their rules appeared in place of the `@import`, i.e. before the
+    rules of the importing sheet. They apply only to the media of both the
+    `@import` rule and the importing sheet.
+    */
+    priv fn insert_sheet_and_imports(&mut self, sheet: Stylesheet, origin: StylesheetOrigin,
+                                     parent_media: u64, position: uint) -> uint {
+        let (sheet, imports, media) = match sheet {
+            Stylesheet { inner: inner, imports: imports, media: media, _ } => (inner, imports, media)
         };
+        let media = parent_media & media.to_net(&self.device);
 
-        self.inner.append_sheet(sheet, origin.to_net(), n::ll::t::CSS_MEDIA_SCREEN)
+        let mut count = 0;
+        for vec::consume(imports) |_, imported| {
+            count += self.insert_sheet_and_imports(imported, origin, media, position + count);
+        }
+        self.inner.insert_sheet(sheet, position + count, origin.to_net(),
+                                media as n::ll::t::css_media_type);
+        count + 1
     }
 
     /**
-    Select the style for a single node. `handler` is used to query the client for
-    a wide range of client-specific details like node relationships, names, and UA
-    defaults.
+    As `insert_sheet_and_imports`, but building new libcss sheets with the
+    rules that apply on the device
+    */
+    priv fn insert_sheet_for_device(&mut self, sheet: &Stylesheet, origin: StylesheetOrigin,
+                                    parent_media: u64, position: uint) -> uint {
+        let media = parent_media & sheet.media.to_net(&self.device);
+
+        let mut count = 0;
+        for sheet.imports.each |imported| {
+            count += self.insert_sheet_for_device(imported, origin, media, position + count);
+        }
+        let inner = net_stylesheet_for_device(sheet, &self.device);
+        self.inner.insert_sheet(inner, position + count, origin.to_net(),
+                                media as n::ll::t::css_media_type);
+        count + 1
+    }
+
+    /** The `@page` rules of `sheet` and its imports that apply to print, in cascade order */
+    priv fn print_page_rules(&self, sheet: &Stylesheet, parent_media: u64) -> ~[PageRule] {
+        let media = parent_media & sheet.media.to_net(&self.device);
+        let mut page_rules = ~[];
+        for sheet.imports.each |imported| {
+            page_rules.push_all(self.print_page_rules(imported, media));
+        }
+        if media & MediaPrint.to_net() != 0 {
+            for sheet.rules().each |rule| {
+                match *rule {
+                    RulePage(ref page_rule) => page_rules.push(copy *page_rule),
+                    _ => ()
+                }
+            }
+        }
+        page_rules
+    }
+
+    /**
+    Compute the page box of the page at `page_index`, counting from 0, when
+    printing. Which `side` of a spread a page is on depends on the
+    document's direction, so the client decides.
+    */
+    fn select_page_style(&self, page_index: uint, side: PageSide) -> PageStyle {
+        let mut page_rules = ~[];
+        for self.sheets.each |appended| {
+            for appended.page_rules.each |page_rule| {
+                page_rules.push((appended.origin, copy *page_rule));
+            }
+        }
+        cascade_page_rules(page_rules, page_index, side)
+    }
+
+    /**
+    Select the style for a single node, as displayed on a screen. `handler` is
+    used to query the client for a wide range of client-specific details like
+    node relationships, names, and UA defaults.
     */
     fn select_style<N: VoidPtrLike, H: SelectHandler<N>>(&self, node: &N, handler: &H) -> SelectResults {
+        self.select_style_inner(node, MediaScreen, None, handler)
+    }
+
+    /**
+    Select the style for a node as rendered on `media`, e.g. `MediaPrint`
+    for a print preview. Only the sheets and `@media` rules that apply to
+    `media` take part. `inline_style` is as for `select_style_with_inline`.
+    */
+    fn select_style_for_media<N: VoidPtrLike, H: SelectHandler<N>>(&self, node: &N,
+                                                                 media: MediaType,
+                                                                 inline_style: Option<&Stylesheet>,
+                                                                 handler: &H) -> SelectResults {
+        let inline_style = inline_style.map(|sheet| &sheet.inner);
+        self.select_style_inner(node, media, inline_style, handler)
+    }
+
+    /**
+    Select the style for a node that carries a `style` attribute. `inline_style`
+    is the attribute parsed with `Stylesheet::from_inline_style`; its
+    declarations cascade as author declarations with the specificity of a
+    style attribute, so they override any selector but not `!important`
+    rules from the sheets.
+    */
+    fn select_style_with_inline<N: VoidPtrLike, H: SelectHandler<N>>(&self, node: &N,
+                                                                    inline_style: &Stylesheet,
+                                                                    handler: &H) -> SelectResults {
+        self.select_style_inner(node, MediaScreen, Some(&inline_style.inner), handler)
+    }
+
+    priv fn select_style_inner<N: VoidPtrLike, H: SelectHandler<N>>(&self, node: &N,
+                                                                  media: MediaType,
+                                                                  inline_style: Option<&CssStylesheet>,
+                                                                  handler: &H) -> SelectResults {
         let inner_handler = SelectHandlerWrapper {
-            inner: ptr::to_unsafe_ptr(handler)
+            inner: ptr::to_unsafe_ptr(handler),
+            hints: handler.node_presentational_hints(node)
         };
+        let media = media.to_net() as n::ll::t::css_media_type;
         SelectResults {
-            inner: self.inner.select_style::<N, SelectHandlerWrapper<N, H>>(node, n::ll::t::CSS_MEDIA_SCREEN, None, &inner_handler)
+            inner: self.inner.select_style::<N, SelectHandlerWrapper<N, H>>(node, media, inline_style, &inner_handler)
         }
     }
 }
@@ -68,12 +368,36 @@ pub struct SelectResults {
 }
 
 pub impl SelectResults {
-    /** Retrieve the computed style of a single pseudo-element */
+    /** Retrieve the computed style of the element itself */
     fn computed_style(&self) -> ComputedStyle/&self {
         ComputedStyle {
             inner: self.inner.computed_style(n::s::CssPseudoElementNone)
         }
     }
+
+    /**
+    Retrieve the computed style of one of the element's pseudo-elements, or
+    `None` if no rule applies to it
+    */
+    fn pseudo_style(&self, pseudo_element: PseudoElement) -> Option<ComputedStyle/&self> {
+        let pseudo_element = net_pseudo_element(pseudo_element);
+        if self.inner.has_computed_style(pseudo_element) {
+            Some(ComputedStyle {
+                inner: self.inner.computed_style(pseudo_element)
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn net_pseudo_element(pseudo_element: PseudoElement) -> n::s::CssPseudoElement {
+    match pseudo_element {
+        PseudoElementFirstLine => n::s::CssPseudoElementFirstLine,
+        PseudoElementFirstLetter => n::s::CssPseudoElementFirstLetter,
+        PseudoElementBefore => n::s::CssPseudoElementBefore,
+        PseudoElementAfter => n::s::CssPseudoElementAfter
+    }
 }
 
 /**
@@ -82,17 +406,153 @@ Callbacks used to query the implementation-specific DOM
 pub trait SelectHandler<N> {
     fn with_node_name<R>(&self, node: &N, f: &fn(&str) -> R) -> R;
     fn with_node_id<R>(&self, node: &N, f: &fn(Option<&str>) -> R) -> R;
-    fn named_parent_node(&self, node: &N, name: &str) -> Option<N>;
     fn parent_node(&self, node: &N) -> Option<N>;
     fn node_has_id(&self, node: &N, &str) -> bool;
-    fn named_ancestor_node(&self, node: &N, name: &str) -> Option<N>;
-    fn node_is_root(&self, node: &N) -> bool;
+
+    // Derived from `parent_node` and `with_node_name`. Override them if the
+    // DOM can answer faster
+
+    /// The parent of `node`, if it is called `name`, for `>`
+    fn named_parent_node(&self, node: &N, name: &str) -> Option<N> {
+        match self.parent_node(node) {
+            Some(parent) => {
+                if self.with_node_name(&parent, |parent_name| parent_name == name) {
+                    Some(parent)
+                } else {
+                    None
+                }
+            }
+            None => None
+        }
+    }
+    /// The closest ancestor of `node` called `name`, for the descendant combinator
+    fn named_ancestor_node(&self, node: &N, name: &str) -> Option<N> {
+        match self.named_parent_node(node, name) {
+            Some(parent) => Some(parent),
+            None => {
+                match self.parent_node(node) {
+                    Some(parent) => self.named_ancestor_node(&parent, name),
+                    None => None
+                }
+            }
+        }
+    }
+    /// `:root`
+    fn node_is_root(&self, node: &N) -> bool { self.parent_node(node).is_none() }
+
+    // Siblings and structure. Without them, sibling combinators and the
+    // structural pseudo-classes never match
+
+    /// The element immediately before `node`, if it is called `name`, for `+`
+    fn named_sibling_node(&self, _node: &N, _name: &str) -> Option<N> { None }
+    /// The closest element before `node` called `name`, for `~`
+    fn named_generic_sibling_node(&self, _node: &N, _name: &str) -> Option<N> { None }
+    /**
+    The number of elements before `node` in its parent, or after it if `after`
+    is set. With `same_name`, only elements of the same name count
+    */
+    fn node_count_siblings(&self, _node: &N, _same_name: bool, _after: bool) -> uint { 0 }
+    /// Whether `node` has no children, elements or text
+    fn node_is_empty(&self, _node: &N) -> bool { false }
+
+    // Classes and attributes. Without them, only type and id selectors match
+
+    /// The classes of `node`, in the order they are written
+    fn with_node_classes<R>(&self, _node: &N, f: &fn(&[&str]) -> R) -> R { f([]) }
+    /// `.name`
+    fn node_has_class(&self, node: &N, name: &str) -> bool {
+        do self.with_node_classes(node) |classes| {
+            classes.any(|class| str::eq_slice(*class, name))
+        }
+    }
+    /// `[name]`
+    fn node_has_attribute(&self, _node: &N, _name: &str) -> bool { false }
+    /// `[name=value]`
+    fn node_has_attribute_equal(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+    /// `[name~=value]`, `value` is one of the whitespace-separated words of the attribute
+    fn node_has_attribute_includes(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+    /// `[name|=value]`, the attribute is `value` or starts with `value` and a hyphen
+    fn node_has_attribute_dashmatch(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+    /// `[name^=value]`
+    fn node_has_attribute_prefix(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+    /// `[name$=value]`
+    fn node_has_attribute_suffix(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+    /// `[name*=value]`
+    fn node_has_attribute_substring(&self, _node: &N, _name: &str, _value: &str) -> bool { false }
+
+    /// The language `node` declares itself, e.g. with a `lang` attribute
+    fn with_node_lang<R>(&self, _node: &N, f: &fn(Option<&str>) -> R) -> R { f(None) }
+    /**
+    `:lang(lang)`. A node without a language of its own is in the language of
+    its parent
+    */
+    fn node_is_lang(&self, node: &N, lang: &str) -> bool {
+        match self.with_node_lang(node, |node_lang| node_lang.map(|l| l.to_owned())) {
+            Some(node_lang) => lang_matches(node_lang, lang),
+            None => {
+                match self.parent_node(node) {
+                    Some(parent) => self.node_is_lang(&parent, lang),
+                    None => false
+                }
+            }
+        }
+    }
+
+    /**
+    The initial value of a property whose initial value depends on the user
+    agent, e.g. `FontFamilyValue(Specified(~[CSSFontFamilyGenericFamily(Serif)]))`.
+    Without one, libcss uses its own
+    */
+    fn ua_default_for_property(&self, _property: Property) -> Option<PropertyValue> { None }
+
+    /**
+    Values for `node`'s presentational attributes, such as `bgcolor`. They
+    take part in the cascade as author declarations with no specificity,
+    before any author sheet. See `html::html_presentational_hints`
+    */
+    fn node_presentational_hints(&self, _node: &N) -> ~[PropertyValue] { ~[] }
+
+    // Dynamic pseudo-classes, CSS 2.1 section 5.11.2-3. Without UI state,
+    // nothing is a link, hovered or focused
+
+    /// `:link`, an unvisited link
+    fn node_is_link(&self, _node: &N) -> bool { false }
+    /// `:visited`
+    fn node_is_visited(&self, _node: &N) -> bool { false }
+    /// `:hover`
+    fn node_is_hover(&self, _node: &N) -> bool { false }
+    /// `:active`
+    fn node_is_active(&self, _node: &N) -> bool { false }
+    /// `:focus`
+    fn node_is_focus(&self, _node: &N) -> bool { false }
+
+    // UI element states, CSS 3 Selectors section 6.6.4
+
+    /// `:enabled`
+    fn node_is_enabled(&self, _node: &N) -> bool { false }
+    /// `:disabled`
+    fn node_is_disabled(&self, _node: &N) -> bool { false }
+    /// `:checked`
+    fn node_is_checked(&self, _node: &N) -> bool { false }
+}
+
+/**
+Whether the language tag `lang` is in the range `range`, by the prefix
+matching of BCP 47: `en` matches `en` and `en-GB` but not `eng`
+*/
+fn lang_matches(lang: &str, range: &str) -> bool {
+    let lang = lang.to_lower();
+    let range = range.to_lower();
+    lang == range || lang.starts_with(range + "-")
 }
 
 /** Used to convert the netsurfcss CssSelectHandler callbacks to out SelectHandler callbacks */
 struct SelectHandlerWrapper<N, H> {
     // FIXME: Can't encode region variables
-    inner: *H
+    inner: *H,
+    /// The presentational hints of the node being selected. libcss asks for
+    /// them one property at a time, so they are only worked out once
+    hints: ~[PropertyValue]
 }
 
 priv impl<N, H: SelectHandler<N>> SelectHandlerWrapper<N, H> {
@@ -134,18 +594,319 @@ impl<N, H: SelectHandler<N>> n::s::CssSelectHandler<N> for SelectHandlerWrapper<
         self.inner_ref().node_is_root(node)
     }
 
-    fn node_is_link(&self, _node: &N) -> bool {
-        // FIXME
-        warn_unimpl("node_is_link");
-        false
+    fn named_sibling_node(&self, node: &N, qname: &n::t::CssQName) -> Option<N> {
+        self.inner_ref().named_sibling_node(node, net_qname_to_rust_str(qname))
+    }
+
+    fn named_generic_sibling_node(&self, node: &N, qname: &n::t::CssQName) -> Option<N> {
+        self.inner_ref().named_generic_sibling_node(node, net_qname_to_rust_str(qname))
+    }
+
+    fn node_count_siblings(&self, node: &N, same_name: bool, after: bool) -> int {
+        self.inner_ref().node_count_siblings(node, same_name, after) as int
+    }
+
+    fn node_is_empty(&self, node: &N) -> bool {
+        self.inner_ref().node_is_empty(node)
+    }
+
+    fn node_classes(&self, node: &N) -> ~[LwcString] {
+        do self.inner_ref().with_node_classes(node) |classes| {
+            classes.map(|class| lwcstr_from_rust_str(*class))
+        }
+    }
+
+    fn node_has_class(&self, node: &N, name: LwcString) -> bool {
+        self.inner_ref().node_has_class(node, name.to_str_slice())
+    }
+
+    fn node_has_attribute(&self, node: &N, qname: &n::t::CssQName) -> bool {
+        self.inner_ref().node_has_attribute(node, net_qname_to_rust_str(qname))
+    }
+
+    fn node_has_attribute_equal(&self, node: &N, qname: &n::t::CssQName,
+                                value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_equal(node, net_qname_to_rust_str(qname),
+                                                  value.to_str_slice())
+    }
+
+    fn node_has_attribute_includes(&self, node: &N, qname: &n::t::CssQName,
+                                   value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_includes(node, net_qname_to_rust_str(qname),
+                                                     value.to_str_slice())
+    }
+
+    fn node_has_attribute_dashmatch(&self, node: &N, qname: &n::t::CssQName,
+                                    value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_dashmatch(node, net_qname_to_rust_str(qname),
+                                                      value.to_str_slice())
+    }
+
+    fn node_has_attribute_prefix(&self, node: &N, qname: &n::t::CssQName,
+                                 value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_prefix(node, net_qname_to_rust_str(qname),
+                                                   value.to_str_slice())
+    }
+
+    fn node_has_attribute_suffix(&self, node: &N, qname: &n::t::CssQName,
+                                 value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_suffix(node, net_qname_to_rust_str(qname),
+                                                   value.to_str_slice())
+    }
+
+    fn node_has_attribute_substring(&self, node: &N, qname: &n::t::CssQName,
+                                    value: LwcString) -> bool {
+        self.inner_ref().node_has_attribute_substring(node, net_qname_to_rust_str(qname),
+                                                      value.to_str_slice())
+    }
+
+    fn node_is_lang(&self, node: &N, lang: LwcString) -> bool {
+        self.inner_ref().node_is_lang(node, lang.to_str_slice())
+    }
+
+    fn node_is_link(&self, node: &N) -> bool {
+        self.inner_ref().node_is_link(node)
+    }
+
+    fn node_is_visited(&self, node: &N) -> bool {
+        self.inner_ref().node_is_visited(node)
+    }
+
+    fn node_is_hover(&self, node: &N) -> bool {
+        self.inner_ref().node_is_hover(node)
+    }
+
+    fn node_is_active(&self, node: &N) -> bool {
+        self.inner_ref().node_is_active(node)
+    }
+
+    fn node_is_focus(&self, node: &N) -> bool {
+        self.inner_ref().node_is_focus(node)
+    }
+
+    fn node_is_enabled(&self, node: &N) -> bool {
+        self.inner_ref().node_is_enabled(node)
+    }
+
+    fn node_is_disabled(&self, node: &N) -> bool {
+        self.inner_ref().node_is_disabled(node)
+    }
+
+    fn node_is_checked(&self, node: &N) -> bool {
+        self.inner_ref().node_is_checked(node)
     }
 
     fn ua_default_for_property(&self, property: n::p::CssProperty) -> n::h::CssHint {
-        warn!("not specifiying ua default for property %?", property);
-        n::h::CssHintDefault
+        let property = match property {
+            n::p::CssPropertyColor => PropertyColor,
+            n::p::CssPropertyFontFamily => PropertyFontFamily,
+            n::p::CssPropertyQuotes => PropertyQuotes,
+            _ => {
+                warn!("not specifiying ua default for property %?", property);
+                return n::h::CssHintDefault;
+            }
+        };
+        match self.inner_ref().ua_default_for_property(property) {
+            Some(value) => {
+                match property_value_to_net_hint(&value) {
+                    Some(hint) => hint,
+                    None => {
+                        warn!("unsupported ua default %?", value);
+                        n::h::CssHintDefault
+                    }
+                }
+            }
+            None => n::h::CssHintDefault
+        }
+    }
+
+    fn node_presentational_hint(&self, _node: &N,
+                                property: n::p::CssProperty) -> Option<n::h::CssHint> {
+        // As with declarations, the last hint for a property wins
+        for self.hints.each_reverse |value| {
+            if net_property(value) == Some(property) {
+                match property_value_to_net_hint(value) {
+                    Some(hint) => return Some(hint),
+                    None => warn!("unsupported presentational hint %?", value)
+                }
+            }
+        }
+        None
+    }
+}
+
+/** The property libcss knows a value as */
+fn net_property(value: &PropertyValue) -> Option<n::p::CssProperty> {
+    match *value {
+        MarginTopValue(_) => Some(n::p::CssPropertyMarginTop),
+        MarginRightValue(_) => Some(n::p::CssPropertyMarginRight),
+        MarginBottomValue(_) => Some(n::p::CssPropertyMarginBottom),
+        MarginLeftValue(_) => Some(n::p::CssPropertyMarginLeft),
+        PaddingTopValue(_) => Some(n::p::CssPropertyPaddingTop),
+        PaddingRightValue(_) => Some(n::p::CssPropertyPaddingRight),
+        PaddingBottomValue(_) => Some(n::p::CssPropertyPaddingBottom),
+        PaddingLeftValue(_) => Some(n::p::CssPropertyPaddingLeft),
+        BorderTopWidthValue(_) => Some(n::p::CssPropertyBorderTopWidth),
+        BorderRightWidthValue(_) => Some(n::p::CssPropertyBorderRightWidth),
+        BorderBottomWidthValue(_) => Some(n::p::CssPropertyBorderBottomWidth),
+        BorderLeftWidthValue(_) => Some(n::p::CssPropertyBorderLeftWidth),
+        BorderTopColorValue(_) => Some(n::p::CssPropertyBorderTopColor),
+        BorderRightColorValue(_) => Some(n::p::CssPropertyBorderRightColor),
+        BorderBottomColorValue(_) => Some(n::p::CssPropertyBorderBottomColor),
+        BorderLeftColorValue(_) => Some(n::p::CssPropertyBorderLeftColor),
+        BorderTopStyleValue(_) => Some(n::p::CssPropertyBorderTopStyle),
+        BorderRightStyleValue(_) => Some(n::p::CssPropertyBorderRightStyle),
+        BorderBottomStyleValue(_) => Some(n::p::CssPropertyBorderBottomStyle),
+        BorderLeftStyleValue(_) => Some(n::p::CssPropertyBorderLeftStyle),
+        FloatValue(_) => Some(n::p::CssPropertyFloat),
+        WidthValue(_) => Some(n::p::CssPropertyWidth),
+        HeightValue(_) => Some(n::p::CssPropertyHeight),
+        ColorValue(_) => Some(n::p::CssPropertyColor),
+        BackgroundColorValue(_) => Some(n::p::CssPropertyBackgroundColor),
+        FontFamilyValue(_) => Some(n::p::CssPropertyFontFamily),
+        QuotesValue(_) => Some(n::p::CssPropertyQuotes),
+        FontSizeValue(_) => Some(n::p::CssPropertyFontSize),
+        TextAlignValue(_) => Some(n::p::CssPropertyTextAlign),
+        _ => None
     }
 }
 
-fn warn_unimpl(what: &str) {
-    warn!("unimplemented select handler: %?", what);
+/**
+The hint libcss expects for a value, as a UA default or a presentational
+hint. Only values HTML and the UA defaults can produce are supported
+*/
+fn property_value_to_net_hint(value: &PropertyValue) -> Option<n::h::CssHint> {
+    match *value {
+        MarginTopValue(Specified(CSSMarginLength(l))) |
+        MarginRightValue(Specified(CSSMarginLength(l))) |
+        MarginBottomValue(Specified(CSSMarginLength(l))) |
+        MarginLeftValue(Specified(CSSMarginLength(l))) |
+        PaddingTopValue(Specified(CSSPaddingLength(l))) |
+        PaddingRightValue(Specified(CSSPaddingLength(l))) |
+        PaddingBottomValue(Specified(CSSPaddingLength(l))) |
+        PaddingLeftValue(Specified(CSSPaddingLength(l))) |
+        BorderTopWidthValue(Specified(CSSBorderWidthLength(l))) |
+        BorderRightWidthValue(Specified(CSSBorderWidthLength(l))) |
+        BorderBottomWidthValue(Specified(CSSBorderWidthLength(l))) |
+        BorderLeftWidthValue(Specified(CSSBorderWidthLength(l))) |
+        WidthValue(Specified(CSSWidthLength(l))) |
+        HeightValue(Specified(CSSHeightLength(l))) |
+        FontSizeValue(Specified(CSSFontSizeLength(l))) => {
+            Some(n::h::CssHintLength(length_to_net_unit(l)))
+        }
+        WidthValue(Specified(CSSWidthPercentage(p))) |
+        HeightValue(Specified(CSSHeightPercentage(p))) |
+        FontSizeValue(Specified(CSSFontSizePercentage(p))) => {
+            Some(n::h::CssHintLength(n::t::CssUnitPct(float_to_css_fixed(p))))
+        }
+        BorderTopColorValue(Specified(CSSBorderColorColor(color))) |
+        BorderRightColorValue(Specified(CSSBorderColorColor(color))) |
+        BorderBottomColorValue(Specified(CSSBorderColorColor(color))) |
+        BorderLeftColorValue(Specified(CSSBorderColorColor(color))) |
+        ColorValue(Specified(CSSColorColor(color))) |
+        BackgroundColorValue(Specified(CSSBackgroundColorColor(color))) => {
+            Some(n::h::CssHintColor(color_to_net(color)))
+        }
+        BorderTopStyleValue(Specified(style)) |
+        BorderRightStyleValue(Specified(style)) |
+        BorderBottomStyleValue(Specified(style)) |
+        BorderLeftStyleValue(Specified(style)) => {
+            Some(n::h::CssHintBorderStyle(border_style_to_net(style)))
+        }
+        FloatValue(Specified(float)) => {
+            Some(n::h::CssHintFloat(match float {
+                CSSFloatLeft => n::v::CssFloatLeft,
+                CSSFloatRight => n::v::CssFloatRight,
+                CSSFloatNone => n::v::CssFloatNone
+            }))
+        }
+        TextAlignValue(Specified(align)) => {
+            Some(n::h::CssHintTextAlign(match align {
+                CSSTextAlignLeft => n::v::CssTextAlignLeft,
+                CSSTextAlignRight => n::v::CssTextAlignRight,
+                CSSTextAlignCenter => n::v::CssTextAlignCenter,
+                CSSTextAlignJustify => n::v::CssTextAlignJustify
+            }))
+        }
+        FontSizeValue(Specified(CSSFontSizeAbsoluteSize(size))) => {
+            Some(n::h::CssHintFontSize(match size {
+                XXSmall => n::v::CssFontSizeXXSmall,
+                XSmall => n::v::CssFontSizeXSmall,
+                Small => n::v::CssFontSizeSmall,
+                Medium => n::v::CssFontSizeMedium,
+                Large => n::v::CssFontSizeLarge,
+                XLarge => n::v::CssFontSizeXLarge,
+                XXLarge => n::v::CssFontSizeXXLarge
+            }))
+        }
+        FontFamilyValue(Specified(ref families)) => {
+            let mut names = ~[];
+            // libcss wants a generic family to fall back to
+            let mut generic = n::v::CssFontFamilySerif;
+            for families.each |family| {
+                match *family {
+                    CSSFontFamilyFamilyName(ref name) => names.push(lwcstr_from_rust_str(*name)),
+                    CSSFontFamilyGenericFamily(family) => {
+                        generic = match family {
+                            Serif => n::v::CssFontFamilySerif,
+                            SansSerif => n::v::CssFontFamilySansSerif,
+                            Cursive => n::v::CssFontFamilyCursive,
+                            Fantasy => n::v::CssFontFamilyFantasy,
+                            Monospace => n::v::CssFontFamilyMonospace
+                        };
+                        break;
+                    }
+                }
+            }
+            Some(n::h::CssHintFontFamily(names, generic))
+        }
+        QuotesValue(Specified(CSSQuotesNone)) => Some(n::h::CssHintQuotes(n::v::CssQuotesNone)),
+        QuotesValue(Specified(CSSQuotesPairs(ref pairs))) => {
+            // libcss wants the strings as one list, open quote first
+            let mut strings = ~[];
+            for pairs.each |pair| {
+                match *pair {
+                    (ref open, ref close) => {
+                        strings.push(lwcstr_from_rust_str(*open));
+                        strings.push(lwcstr_from_rust_str(*close));
+                    }
+                }
+            }
+            Some(n::h::CssHintQuotes(n::v::CssQuotesString(strings)))
+        }
+        _ => None
+    }
+}
+
+fn length_to_net_unit(length: Length) -> n::t::CssUnit {
+    match length {
+        Px(v) => n::t::CssUnitPx(float_to_css_fixed(v)),
+        Em(v) => n::t::CssUnitEm(float_to_css_fixed(v)),
+        Ex(v) => n::t::CssUnitEx(float_to_css_fixed(v)),
+        Pt(v) => n::t::CssUnitPt(float_to_css_fixed(v))
+    }
+}
+
+fn color_to_net(color: Color) -> n::t::CssColor {
+    n::t::CssColor {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+        a: (color.alpha * 255.0 + 0.5) as u8
+    }
+}
+
+fn border_style_to_net(style: CSSBorderStyle) -> n::v::CssBorderStyleValue {
+    match style {
+        CSSBorderStyleNone => n::v::CssBorderStyleNone,
+        CSSBorderStyleHidden => n::v::CssBorderStyleHidden,
+        CSSBorderStyleDotted => n::v::CssBorderStyleDotted,
+        CSSBorderStyleDashed => n::v::CssBorderStyleDashed,
+        CSSBorderStyleSolid => n::v::CssBorderStyleSolid,
+        CSSBorderStyleDouble => n::v::CssBorderStyleDouble,
+        CSSBorderStyleGroove => n::v::CssBorderStyleGroove,
+        CSSBorderStyleRidge => n::v::CssBorderStyleRidge,
+        CSSBorderStyleInset => n::v::CssBorderStyleInset,
+        CSSBorderStyleOutset => n::v::CssBorderStyleOutset
+    }
 }
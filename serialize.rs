@@ -0,0 +1,937 @@
+/*!
+Writing parsed rules back out as CSS text.
+
+The output is normalized rather than a copy of the source: shorthands come
+out as their longhands, keywords and type selectors are lower case, lengths
+are in the units they were converted to and colors are written by `Color`.
+Parsing the output again gives the same rules.
+*/
+
+use color::Color;
+use units::*;
+use values::*;
+use types::{MediaList, MediaType, MediaAll, MediaAural, MediaBraille, MediaEmbossed};
+use types::{MediaHandheld, MediaPrint, MediaProjection, MediaScreen, MediaSpeech, MediaTty};
+use types::{MediaTv, PseudoElement, PseudoElementFirstLine, PseudoElementFirstLetter};
+use types::{PseudoElementBefore, PseudoElementAfter};
+use properties::*;
+use media_queries::*;
+use rules::*;
+use syntax::{Token, Ident, AtKeyword, QuotedString, BadString, Hash, Number, Percentage};
+use syntax::{Dimension, Uri, BadUri, UnicodeRange, CDO, CDC, Colon, Semicolon, LeftBrace};
+use syntax::{RightBrace, LeftParen, RightParen, LeftBracket, RightBracket, Whitespace, Function};
+use syntax::{Includes, DashMatch, PrefixMatch, SuffixMatch, SubstringMatch, Delim};
+use syntax::{is_name_start, is_name_char, is_digit, is_newline};
+
+/** Something that can be written as CSS text */
+pub trait ToCss {
+    fn to_css(&self) -> ~str;
+}
+
+impl ToCss for Rule {
+    fn to_css(&self) -> ~str {
+        match *self {
+            RuleStyle(ref rule) => rule.to_css(),
+            RuleMedia(ref rule) => rule.to_css(),
+            RuleImport(ref rule) => rule.to_css(),
+            RuleFontFace(ref rule) => rule.to_css(),
+            RulePage(ref rule) => rule.to_css()
+        }
+    }
+}
+
+impl ToCss for StyleRule {
+    fn to_css(&self) -> ~str {
+        // The rule of a style attribute is just its declarations
+        if self.selectors.is_empty() {
+            return str::connect(self.declarations.map(|d| d.to_css()), " ");
+        }
+        let selectors = self.selectors.map(|s| s.to_css());
+        str::connect(selectors, ", ") + " " + declaration_block(self.declarations)
+    }
+}
+
+impl ToCss for MediaRule {
+    fn to_css(&self) -> ~str {
+        let mut css = ~"@media " + self.media.to_css() + " {";
+        for self.rules.each |rule| {
+            str::push_str(&mut css, " ");
+            str::push_str(&mut css, rule.to_css());
+        }
+        css + " }"
+    }
+}
+
+impl ToCss for ImportRule {
+    fn to_css(&self) -> ~str {
+        let mut css = ~"@import url(" + escape_string(self.url.to_str()) + ")";
+        if self.media != MediaList::all() {
+            str::push_str(&mut css, " ");
+            str::push_str(&mut css, self.media.to_css());
+        }
+        css + ";"
+    }
+}
+
+impl ToCss for FontFaceRule {
+    fn to_css(&self) -> ~str {
+        let mut css = ~"@font-face { font-family: " + escape_string(self.family) + ";";
+        let sources = str::connect(self.sources.map(|s| s.to_css()), ", ");
+        str::push_str(&mut css, fmt!(" src: %s;", sources));
+        // Descriptors with their initial values are left out
+        if self.weight != CSSFontWeightNormal {
+            str::push_str(&mut css, fmt!(" font-weight: %s;", self.weight.to_css()));
+        }
+        if self.style != CSSFontStyleNormal {
+            str::push_str(&mut css, fmt!(" font-style: %s;", self.style.to_css()));
+        }
+        if self.unicode_range != ~[(0, 0x10FFFF)] {
+            let ranges = do self.unicode_range.map |&(first, last)| {
+                if first == last {
+                    fmt!("U+%X", first as uint)
+                } else {
+                    fmt!("U+%X-%X", first as uint, last as uint)
+                }
+            };
+            str::push_str(&mut css, fmt!(" unicode-range: %s;", str::connect(ranges, ", ")));
+        }
+        css + " }"
+    }
+}
+
+impl ToCss for FontFaceSource {
+    fn to_css(&self) -> ~str {
+        match *self {
+            FontFaceSourceUrl(ref url, ref formats) => {
+                let mut css = ~"url(" + escape_string(url.to_str()) + ")";
+                if !formats.is_empty() {
+                    let formats = formats.map(|f| escape_string(*f));
+                    str::push_str(&mut css, fmt!(" format(%s)", str::connect(formats, ", ")));
+                }
+                css
+            }
+            FontFaceSourceLocal(ref name) => ~"local(" + escape_string(*name) + ")"
+        }
+    }
+}
+
+impl ToCss for PageRule {
+    fn to_css(&self) -> ~str {
+        let selector = match self.selector {
+            Some(PageFirst) => " :first",
+            Some(PageLeft) => " :left",
+            Some(PageRight) => " :right",
+            None => ""
+        };
+        ~"@page" + selector + " " + declaration_block(self.declarations)
+    }
+}
+
+fn declaration_block(declarations: &[PropertyDeclaration]) -> ~str {
+    let mut css = ~"{";
+    for declarations.each |declaration| {
+        str::push_str(&mut css, " ");
+        str::push_str(&mut css, declaration.to_css());
+    }
+    css + " }"
+}
+
+impl ToCss for MediaList {
+    fn to_css(&self) -> ~str {
+        str::connect(self.queries.map(|q| q.to_css()), ", ")
+    }
+}
+
+impl ToCss for MediaQuery {
+    fn to_css(&self) -> ~str {
+        let mut parts = ~[];
+        if self.negated {
+            parts.push(~"not " + self.media_type.to_css());
+        } else if self.media_type != MediaAll || self.expressions.is_empty() {
+            parts.push(self.media_type.to_css());
+        }
+        for self.expressions.each |expression| {
+            parts.push(expression.to_css());
+        }
+        str::connect(parts, " and ")
+    }
+}
+
+impl ToCss for MediaFeature {
+    fn to_css(&self) -> ~str {
+        let css = match *self {
+            FeatureWidth(ref range) => range_to_css("width", range.map(|l| l.to_css())),
+            FeatureHeight(ref range) => range_to_css("height", range.map(|l| l.to_css())),
+            FeatureDeviceWidth(ref range) => range_to_css("device-width", range.map(|l| l.to_css())),
+            FeatureDeviceHeight(ref range) => range_to_css("device-height", range.map(|l| l.to_css())),
+            FeatureOrientation(OrientationPortrait) => ~"orientation: portrait",
+            FeatureOrientation(OrientationLandscape) => ~"orientation: landscape",
+            FeatureAspectRatio(ref range) => range_to_css("aspect-ratio", range.map(ratio_to_css)),
+            FeatureDeviceAspectRatio(ref range) => {
+                range_to_css("device-aspect-ratio", range.map(ratio_to_css))
+            }
+            FeatureColor(ref range) => range_to_css("color", range.map(|c| c.to_str())),
+            FeatureColorIndex(ref range) => range_to_css("color-index", range.map(|c| c.to_str())),
+            FeatureMonochrome(ref range) => range_to_css("monochrome", range.map(|m| m.to_str())),
+            FeatureResolution(ref range) => {
+                range_to_css("resolution", range.map(|r| format_number(*r) + "dppx"))
+            }
+            FeatureScan(ScanProgressive) => ~"scan: progressive",
+            FeatureScan(ScanInterlace) => ~"scan: interlace",
+            FeatureGrid(true) => ~"grid",
+            FeatureGrid(false) => ~"grid: 0"
+        };
+        ~"(" + css + ")"
+    }
+}
+
+fn range_to_css(name: &str, range: Range<~str>) -> ~str {
+    match range {
+        RangeMin(value) => ~"min-" + name + ": " + value,
+        RangeMax(value) => ~"max-" + name + ": " + value,
+        RangeExact(value) => name.to_owned() + ": " + value,
+        RangeNonZero => name.to_owned()
+    }
+}
+
+fn ratio_to_css(&(w, h): &(uint, uint)) -> ~str {
+    fmt!("%u/%u", w, h)
+}
+
+impl ToCss for MediaType {
+    fn to_css(&self) -> ~str {
+        match *self {
+            MediaAll => ~"all",
+            MediaAural => ~"aural",
+            MediaBraille => ~"braille",
+            MediaEmbossed => ~"embossed",
+            MediaHandheld => ~"handheld",
+            MediaPrint => ~"print",
+            MediaProjection => ~"projection",
+            MediaScreen => ~"screen",
+            MediaSpeech => ~"speech",
+            MediaTty => ~"tty",
+            MediaTv => ~"tv"
+        }
+    }
+}
+
+impl ToCss for Selector {
+    fn to_css(&self) -> ~str {
+        let mut css = self.first.to_css();
+        for self.rest.each |&(combinator, ref compound)| {
+            str::push_str(&mut css, match combinator {
+                DescendantCombinator => " ",
+                ChildCombinator => " > ",
+                AdjacentSiblingCombinator => " + ",
+                GeneralSiblingCombinator => " ~ "
+            });
+            str::push_str(&mut css, compound.to_css());
+        }
+        match self.pseudo_element {
+            Some(ref pseudo) => css + pseudo.to_css(),
+            None => css
+        }
+    }
+}
+
+impl ToCss for CompoundSelector {
+    fn to_css(&self) -> ~str {
+        let mut css = match self.element {
+            Some(ref name) => escape_ident(*name),
+            None if self.conditions.is_empty() => ~"*",
+            None => ~""
+        };
+        for self.conditions.each |condition| {
+            str::push_str(&mut css, condition.to_css());
+        }
+        css
+    }
+}
+
+impl ToCss for SimpleSelector {
+    fn to_css(&self) -> ~str {
+        match *self {
+            IdSelector(ref id) => ~"#" + escape_ident(*id),
+            ClassSelector(ref class) => ~"." + escape_ident(*class),
+            AttributeSelector(ref name, ref test) => {
+                let test = match *test {
+                    AttributeExists => ~"",
+                    AttributeEqual(ref value) => ~"=" + escape_string(*value),
+                    AttributeIncludes(ref value) => ~"~=" + escape_string(*value),
+                    AttributeDashMatch(ref value) => ~"|=" + escape_string(*value),
+                    AttributePrefix(ref value) => ~"^=" + escape_string(*value),
+                    AttributeSuffix(ref value) => ~"$=" + escape_string(*value),
+                    AttributeSubstring(ref value) => ~"*=" + escape_string(*value)
+                };
+                ~"[" + escape_ident(*name) + test + "]"
+            }
+            PseudoClassSelector(ref pseudo) => pseudo.to_css()
+        }
+    }
+}
+
+impl ToCss for PseudoClass {
+    fn to_css(&self) -> ~str {
+        match *self {
+            PseudoLink => ~":link",
+            PseudoVisited => ~":visited",
+            PseudoHover => ~":hover",
+            PseudoActive => ~":active",
+            PseudoFocus => ~":focus",
+            PseudoFirstChild => ~":first-child",
+            PseudoLastChild => ~":last-child",
+            PseudoOnlyChild => ~":only-child",
+            PseudoFirstOfType => ~":first-of-type",
+            PseudoLastOfType => ~":last-of-type",
+            PseudoOnlyOfType => ~":only-of-type",
+            PseudoRoot => ~":root",
+            PseudoEmpty => ~":empty",
+            PseudoTarget => ~":target",
+            PseudoEnabled => ~":enabled",
+            PseudoDisabled => ~":disabled",
+            PseudoChecked => ~":checked",
+            PseudoLang(ref lang) => ~":lang(" + escape_ident(*lang) + ")",
+            PseudoNthChild(a, b) => ~":nth-child(" + nth_to_css(a, b) + ")",
+            PseudoNthLastChild(a, b) => ~":nth-last-child(" + nth_to_css(a, b) + ")",
+            PseudoNthOfType(a, b) => ~":nth-of-type(" + nth_to_css(a, b) + ")",
+            PseudoNthLastOfType(a, b) => ~":nth-last-of-type(" + nth_to_css(a, b) + ")",
+            PseudoNot(ref negated) => ~":not(" + negated.to_css() + ")"
+        }
+    }
+}
+
+fn nth_to_css(a: int, b: int) -> ~str {
+    let a_part = match a {
+        0 => return fmt!("%d", b),
+        1 => ~"n",
+        -1 => ~"-n",
+        a => fmt!("%dn", a)
+    };
+    if b > 0 {
+        fmt!("%s+%d", a_part, b)
+    } else if b < 0 {
+        fmt!("%s%d", a_part, b)
+    } else {
+        a_part
+    }
+}
+
+impl ToCss for PseudoElement {
+    fn to_css(&self) -> ~str {
+        match *self {
+            PseudoElementFirstLine => ~"::first-line",
+            PseudoElementFirstLetter => ~"::first-letter",
+            PseudoElementBefore => ~"::before",
+            PseudoElementAfter => ~"::after"
+        }
+    }
+}
+
+impl ToCss for PropertyDeclaration {
+    fn to_css(&self) -> ~str {
+        let important = if self.important { " !important" } else { "" };
+        fmt!("%s: %s%s;", self.value.name(), self.value.to_css(), important)
+    }
+}
+
+/** Writes just the value, without the property name */
+impl ToCss for PropertyValue {
+    fn to_css(&self) -> ~str {
+        match *self {
+            MarginTopValue(ref v) | MarginRightValue(ref v) | MarginBottomValue(ref v) |
+            MarginLeftValue(ref v) => v.to_css(),
+            PaddingTopValue(ref v) | PaddingRightValue(ref v) | PaddingBottomValue(ref v) |
+            PaddingLeftValue(ref v) => v.to_css(),
+            BorderTopWidthValue(ref v) | BorderRightWidthValue(ref v) |
+            BorderBottomWidthValue(ref v) | BorderLeftWidthValue(ref v) => v.to_css(),
+            BorderTopColorValue(ref v) | BorderRightColorValue(ref v) |
+            BorderBottomColorValue(ref v) | BorderLeftColorValue(ref v) => v.to_css(),
+            BorderTopStyleValue(ref v) | BorderRightStyleValue(ref v) |
+            BorderBottomStyleValue(ref v) | BorderLeftStyleValue(ref v) => v.to_css(),
+            DisplayValue(ref v) => v.to_css(),
+            PositionValue(ref v) => v.to_css(),
+            TopValue(ref v) => v.to_css(),
+            RightValue(ref v) => v.to_css(),
+            BottomValue(ref v) => v.to_css(),
+            LeftValue(ref v) => v.to_css(),
+            FloatValue(ref v) => v.to_css(),
+            DirectionValue(ref v) => v.to_css(),
+            WidthValue(ref v) => v.to_css(),
+            HeightValue(ref v) => v.to_css(),
+            LineHeightValue(ref v) => v.to_css(),
+            VerticalAlignValue(ref v) => v.to_css(),
+            OverflowValue(ref v) => v.to_css(),
+            VisibilityValue(ref v) => v.to_css(),
+            QuotesValue(ref v) => v.to_css(),
+            PageBreakBeforeValue(ref v) => v.to_css(),
+            PageBreakAfterValue(ref v) => v.to_css(),
+            PageBreakInsideValue(ref v) => v.to_css(),
+            OrphansValue(ref v) => v.to_css(),
+            WidowsValue(ref v) => v.to_css(),
+            SizeValue(ref v) => v.to_css(),
+            ColorValue(ref v) => v.to_css(),
+            BackgroundColorValue(ref v) => v.to_css(),
+            BackgroundImageValue(ref v) => v.to_css(),
+            BackgroundRepeatValue(ref v) => v.to_css(),
+            BackgroundAttachmentValue(ref v) => v.to_css(),
+            BackgroundPositionValue(Inherit) => ~"inherit",
+            BackgroundPositionValue(Specified((ref h, ref v))) => h.to_css() + " " + v.to_css(),
+            FontFamilyValue(Inherit) => ~"inherit",
+            FontFamilyValue(Specified(ref families)) => {
+                str::connect(families.map(|f| f.to_css()), ", ")
+            }
+            FontStyleValue(ref v) => v.to_css(),
+            FontWeightValue(ref v) => v.to_css(),
+            FontSizeValue(ref v) => v.to_css(),
+            TextAlignValue(ref v) => v.to_css(),
+            TextDecorationValue(Inherit) => ~"inherit",
+            TextDecorationValue(Specified(ref decorations)) => {
+                str::connect(decorations.map(|d| d.to_css()), " ")
+            }
+            TextTransformValue(ref v) => v.to_css(),
+            UnparsedValue(_, ref text) => copy *text
+        }
+    }
+}
+
+impl<T: ToCss> ToCss for CSSValue<T> {
+    fn to_css(&self) -> ~str {
+        match *self {
+            Inherit => ~"inherit",
+            Specified(ref value) => value.to_css()
+        }
+    }
+}
+
+impl ToCss for Length {
+    fn to_css(&self) -> ~str {
+        match *self {
+            Px(0.0) => ~"0",
+            Px(v) => format_number(v) + "px",
+            Em(v) => format_number(v) + "em",
+            Ex(v) => format_number(v) + "ex",
+            Pt(v) => format_number(v) + "pt"
+        }
+    }
+}
+
+fn percentage_to_css(p: float) -> ~str {
+    format_number(p) + "%"
+}
+
+impl ToCss for Color {
+    fn to_css(&self) -> ~str {
+        self.print()
+    }
+}
+
+impl ToCss for CSSMargin {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSMarginLength(ref l) => l.to_css(),
+            CSSMarginPercentage(p) => percentage_to_css(p),
+            CSSMarginAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSPadding {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSPaddingLength(ref l) => l.to_css(),
+            CSSPaddingPercentage(p) => percentage_to_css(p)
+        }
+    }
+}
+
+impl ToCss for CSSBorderWidth {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBorderWidthThin => ~"thin",
+            CSSBorderWidthMedium => ~"medium",
+            CSSBorderWidthThick => ~"thick",
+            CSSBorderWidthLength(ref l) => l.to_css()
+        }
+    }
+}
+
+impl ToCss for CSSBorderColor {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBorderColorColor(ref c) => c.to_css(),
+            CSSBorderColorTransparent => ~"transparent"
+        }
+    }
+}
+
+impl ToCss for CSSBorderStyle {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBorderStyleNone => ~"none",
+            CSSBorderStyleHidden => ~"hidden",
+            CSSBorderStyleDotted => ~"dotted",
+            CSSBorderStyleDashed => ~"dashed",
+            CSSBorderStyleSolid => ~"solid",
+            CSSBorderStyleDouble => ~"double",
+            CSSBorderStyleGroove => ~"groove",
+            CSSBorderStyleRidge => ~"ridge",
+            CSSBorderStyleInset => ~"inset",
+            CSSBorderStyleOutset => ~"outset"
+        }
+    }
+}
+
+impl ToCss for CSSDisplay {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSDisplayInline => ~"inline",
+            CSSDisplayBlock => ~"block",
+            CSSDisplayListItem => ~"list-item",
+            CSSDisplayInlineBlock => ~"inline-block",
+            CSSDisplayTable => ~"table",
+            CSSDisplayInlineTable => ~"inline-table",
+            CSSDisplayTableRowGroup => ~"table-row-group",
+            CSSDisplayTableHeaderGroup => ~"table-header-group",
+            CSSDisplayTableFooterGroup => ~"table-footer-group",
+            CSSDisplayTableRow => ~"table-row",
+            CSSDisplayTableColumnGroup => ~"table-column-group",
+            CSSDisplayTableColumn => ~"table-column",
+            CSSDisplayTableCell => ~"table-cell",
+            CSSDisplayTableCaption => ~"table-caption",
+            CSSDisplayNone => ~"none"
+        }
+    }
+}
+
+impl ToCss for CSSPosition {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSPositionStatic => ~"static",
+            CSSPositionRelative => ~"relative",
+            CSSPositionAbsolute => ~"absolute",
+            CSSPositionFixed => ~"fixed"
+        }
+    }
+}
+
+impl ToCss for CSSTop {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSTopLength(ref l) => l.to_css(),
+            // The parser keeps percentages as text instead
+            CSSTopPercentage => fail!(~"CSSTopPercentage has no percentage to write"),
+            CSSTopAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSRight {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSRightLength(ref l) => l.to_css(),
+            CSSRightPercentage(p) => percentage_to_css(p),
+            CSSRightAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSBottom {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBottomLength(ref l) => l.to_css(),
+            CSSBottomPercentage(p) => percentage_to_css(p),
+            CSSBottomAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSLeft {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSLeftLength(ref l) => l.to_css(),
+            CSSLeftPercentage(p) => percentage_to_css(p),
+            CSSLeftAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSFloat {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSFloatLeft => ~"left",
+            CSSFloatRight => ~"right",
+            CSSFloatNone => ~"none"
+        }
+    }
+}
+
+impl ToCss for CSSDirection {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSDirectionLtr => ~"ltr",
+            CSSDirectionRtl => ~"rtl"
+        }
+    }
+}
+
+impl ToCss for CSSWidth {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSWidthLength(ref l) => l.to_css(),
+            CSSWidthPercentage(p) => percentage_to_css(p),
+            CSSWidthAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSHeight {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSHeightLength(ref l) => l.to_css(),
+            CSSHeightPercentage(p) => percentage_to_css(p),
+            CSSHeightAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSLineHeight {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSLineHeightNormal => ~"normal",
+            CSSLineHeightNumber(n) => format_number(n),
+            CSSLineHeightLength(ref l) => l.to_css(),
+            CSSLineHeightPercentage(p) => percentage_to_css(p)
+        }
+    }
+}
+
+impl ToCss for CSSVerticalAlign {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSVerticalAlignBaseline => ~"baseline",
+            CSSVerticalAlignSub => ~"sub",
+            CSSVerticalAlignSuper => ~"super",
+            CSSVerticalAlignTop => ~"top",
+            CSSVerticalAlignTextTop => ~"text-top",
+            CSSVerticalAlignMiddle => ~"middle",
+            CSSVerticalAlignBottom => ~"bottom",
+            CSSVerticalAlignTextBottom => ~"text-bottom",
+            CSSVerticalAlignPercentage(p) => percentage_to_css(p),
+            CSSVerticalAlignLength(ref l) => l.to_css()
+        }
+    }
+}
+
+impl ToCss for CSSOverflow {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSOverflowVisible => ~"visible",
+            CSSOverflowHidden => ~"hidden",
+            CSSOverflowScroll => ~"scroll",
+            CSSOverflowAuto => ~"auto"
+        }
+    }
+}
+
+impl ToCss for CSSVisibility {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSVisibilityVisible => ~"visible",
+            CSSVisibilityHidden => ~"hidden",
+            CSSVisibilityCollapse => ~"collapse"
+        }
+    }
+}
+
+impl ToCss for CSSQuotes {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSQuotesNone => ~"none",
+            CSSQuotesPairs(ref pairs) => {
+                let pairs = do pairs.map |pair| {
+                    match *pair {
+                        (ref open, ref close) => escape_string(*open) + " " + escape_string(*close)
+                    }
+                };
+                str::connect(pairs, " ")
+            }
+        }
+    }
+}
+
+impl ToCss for CSSPageBreak {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSPageBreakAuto => ~"auto",
+            CSSPageBreakAlways => ~"always",
+            CSSPageBreakAvoid => ~"avoid",
+            CSSPageBreakLeft => ~"left",
+            CSSPageBreakRight => ~"right"
+        }
+    }
+}
+
+impl ToCss for CSSPageBreakInside {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSPageBreakInsideAuto => ~"auto",
+            CSSPageBreakInsideAvoid => ~"avoid"
+        }
+    }
+}
+
+impl ToCss for CSSPageSize {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSPageSizeAuto => ~"auto",
+            CSSPageSizePortrait => ~"portrait",
+            CSSPageSizeLandscape => ~"landscape",
+            CSSPageSizeLength(width, height) => width.to_css() + " " + height.to_css()
+        }
+    }
+}
+
+impl ToCss for uint {
+    fn to_css(&self) -> ~str {
+        self.to_str()
+    }
+}
+
+impl ToCss for CSSColor {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSColorColor(ref c) => c.to_css()
+        }
+    }
+}
+
+impl ToCss for CSSBackgroundColor {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBackgroundColorColor(ref c) => c.to_css(),
+            CSSBackgroundColorTransparent => ~"transparent"
+        }
+    }
+}
+
+impl ToCss for CSSBackgroundImage {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBackgroundUri(ref url) => ~"url(" + escape_string(url.to_str()) + ")",
+            CSSBackgroundImageNone => ~"none"
+        }
+    }
+}
+
+impl ToCss for CSSBackgroundRepeat {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBackgroundRepeatRepeat => ~"repeat",
+            CSSBackgroundRepeatRepeatX => ~"repeat-x",
+            CSSBackgroundRepeatRepeatY => ~"repeat-y",
+            CSSBackgroundRepeatNoRepeat => ~"no-repeat"
+        }
+    }
+}
+
+impl ToCss for CSSBackgroundAttachment {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBackgroundAttachmentScroll => ~"scroll",
+            CSSBackgroundAttachmentFixed => ~"fixed"
+        }
+    }
+}
+
+impl ToCss for CSSBackgroundPosition {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSBackgroundPositionPercentage(p) => percentage_to_css(p),
+            CSSBackgroundPositionLength(ref l) => l.to_css(),
+            CSSBackgroundPositionLeft => ~"left",
+            CSSBackgroundPositionCenter => ~"center",
+            CSSBackgroundPositionRight => ~"right",
+            CSSBackgroundPositionTop => ~"top",
+            CSSBackgroundPositionBottom => ~"bottom"
+        }
+    }
+}
+
+impl ToCss for CSSFontFamily {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSFontFamilyFamilyName(ref name) => escape_string(*name),
+            CSSFontFamilyGenericFamily(Serif) => ~"serif",
+            CSSFontFamilyGenericFamily(SansSerif) => ~"sans-serif",
+            CSSFontFamilyGenericFamily(Cursive) => ~"cursive",
+            CSSFontFamilyGenericFamily(Fantasy) => ~"fantasy",
+            CSSFontFamilyGenericFamily(Monospace) => ~"monospace"
+        }
+    }
+}
+
+impl ToCss for CSSFontStyle {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSFontStyleNormal => ~"normal",
+            CSSFontStyleItalic => ~"italic",
+            CSSFontStyleOblique => ~"oblique"
+        }
+    }
+}
+
+impl ToCss for CSSFontWeight {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSFontWeightNormal => ~"normal",
+            CSSFontWeightBold => ~"bold",
+            CSSFontWeightBolder => ~"bolder",
+            CSSFontWeightLighter => ~"lighter",
+            CSSFontWeight100 => ~"100",
+            CSSFontWeight200 => ~"200",
+            CSSFontWeight300 => ~"300",
+            CSSFontWeight400 => ~"400",
+            CSSFontWeight500 => ~"500",
+            CSSFontWeight600 => ~"600",
+            CSSFontWeight700 => ~"700",
+            CSSFontWeight800 => ~"800",
+            CSSFontWeight900 => ~"900"
+        }
+    }
+}
+
+impl ToCss for CSSFontSize {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSFontSizeAbsoluteSize(XXSmall) => ~"xx-small",
+            CSSFontSizeAbsoluteSize(XSmall) => ~"x-small",
+            CSSFontSizeAbsoluteSize(Small) => ~"small",
+            CSSFontSizeAbsoluteSize(Medium) => ~"medium",
+            CSSFontSizeAbsoluteSize(Large) => ~"large",
+            CSSFontSizeAbsoluteSize(XLarge) => ~"x-large",
+            CSSFontSizeAbsoluteSize(XXLarge) => ~"xx-large",
+            CSSFontSizeRelativeSize(Larger) => ~"larger",
+            CSSFontSizeRelativeSize(Smaller) => ~"smaller",
+            CSSFontSizeLength(ref l) => l.to_css(),
+            CSSFontSizePercentage(p) => percentage_to_css(p)
+        }
+    }
+}
+
+impl ToCss for CSSTextAlign {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSTextAlignLeft => ~"left",
+            CSSTextAlignRight => ~"right",
+            CSSTextAlignCenter => ~"center",
+            CSSTextAlignJustify => ~"justify"
+        }
+    }
+}
+
+impl ToCss for CSSTextDecoration {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSTextDecorationNone => ~"none",
+            CSSTextDecorationUnderline => ~"underline",
+            CSSTextDecorationOverline => ~"overline",
+            CSSTextDecorationLineThrough => ~"line-through",
+            CSSTextDecorationBlink => ~"blink"
+        }
+    }
+}
+
+impl ToCss for CSSTextTransform {
+    fn to_css(&self) -> ~str {
+        match *self {
+            CSSTextTransformCapitalize => ~"capitalize",
+            CSSTextTransformUppercase => ~"uppercase",
+            CSSTextTransformLowercase => ~"lowercase",
+            CSSTextTransformNone => ~"none"
+        }
+    }
+}
+
+/** Write `name` as an identifier, escaping characters as needed */
+pub fn escape_ident(name: &str) -> ~str {
+    let mut result = ~"";
+    let chars = str::chars(name);
+    for chars.eachi |i, &c| {
+        let needs_escape = if i == 0 {
+            !(is_name_start(c) || (c == '-' && chars.len() > 1 && !is_digit(chars[1])))
+        } else {
+            !is_name_char(c)
+        };
+        if needs_escape && (is_digit(c) || is_newline(c) || c < ' ') {
+            str::push_str(&mut result, fmt!("\\%x ", c as uint));
+        } else if needs_escape {
+            str::push_char(&mut result, '\\');
+            str::push_char(&mut result, c);
+        } else {
+            str::push_char(&mut result, c);
+        }
+    }
+    result
+}
+
+/** Write `value` as a double-quoted string */
+pub fn escape_string(value: &str) -> ~str {
+    let mut result = ~"\"";
+    for str::each_char(value) |c| {
+        match c {
+            '"' => str::push_str(&mut result, "\\\""),
+            '\\' => str::push_str(&mut result, "\\\\"),
+            c if is_newline(c) || c < ' ' => str::push_str(&mut result, fmt!("\\%x ", c as uint)),
+            c => str::push_char(&mut result, c)
+        }
+    }
+    str::push_char(&mut result, '"');
+    result
+}
+
+/** Write a number without trailing zeroes, e.g. `1.5` or `10` */
+pub fn format_number(value: float) -> ~str {
+    if value == (value as int) as float {
+        fmt!("%d", value as int)
+    } else {
+        // Enough places that converted units like `cm` read back the same
+        float::to_str_digits(value, 15)
+    }
+}
+
+impl ToStr for Token {
+    fn to_str(&self) -> ~str {
+        match *self {
+            Ident(ref name) => escape_ident(*name),
+            AtKeyword(ref name) => ~"@" + escape_ident(*name),
+            QuotedString(ref value) => escape_string(*value),
+            BadString => ~"\"",
+            Hash(ref name) => ~"#" + escape_ident(*name),
+            Number(value) => format_number(value),
+            Percentage(value) => format_number(value) + "%",
+            Dimension(value, ref unit) => format_number(value) + escape_ident(*unit),
+            Uri(ref url) => ~"url(" + escape_string(*url) + ")",
+            BadUri => ~"url(",
+            UnicodeRange(ref range) => copy *range,
+            CDO => ~"<!--",
+            CDC => ~"-->",
+            Colon => ~":",
+            Semicolon => ~";",
+            LeftBrace => ~"{",
+            RightBrace => ~"}",
+            LeftParen => ~"(",
+            RightParen => ~")",
+            LeftBracket => ~"[",
+            RightBracket => ~"]",
+            Whitespace => ~" ",
+            Function(ref name) => escape_ident(*name) + "(",
+            Includes => ~"~=",
+            DashMatch => ~"|=",
+            PrefixMatch => ~"^=",
+            SuffixMatch => ~"$=",
+            SubstringMatch => ~"*=",
+            Delim(c) => str::from_char(c)
+        }
+    }
+}
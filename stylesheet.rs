@@ -1,20 +1,475 @@
 /*!
-CSS stylesheets, owned types, immutable after creation
+CSS stylesheets, owned types. Rules can be added and removed after
+creation with `insert_rule` and `delete_rule`, though libcss parses the
+whole sheet again for each change.
 */
 
 use std::net::url::Url;
-use util::DataStream;
+use core::io::{Reader, Writer, WriterUtil};
+use util::{DataStream, bytes_stream, reader_stream};
+use core::clone::Clone;
+use color::Color;
+use types::{MediaList, EncodingSource, LanguageLevel, Level21};
 use netsurfcss::stylesheet::CssStylesheet;
-use parser::parse_stylesheet;
+use parser::{parse_stylesheet, parse_stylesheet_str, parse_inline_style, rebuild_net_stylesheet};
+use syntax::{ParseDiagnostic, Span, RuleSpan, parse_rules};
+use rules::{Rule, RuleImport, FontFaceRule, rule_from_raw, font_face_rules};
+use serialize::ToCss;
+use media_queries::{Device, rules_depend_on_device, rules_changed_by_device};
+
+/// How many bytes `Stylesheet::from_reader` reads at a time
+pub const DEFAULT_CHUNK_SIZE: uint = 8192;
 
 pub struct Stylesheet {
-    inner: CssStylesheet
+    inner: CssStylesheet,
+    /// The sheet's own url, the base for the urls in it
+    url: Url,
+    level: LanguageLevel,
+    allow_quirks: bool,
+    /// Whether this is the contents of a `style` attribute
+    inline_style: bool,
+    /// Sheets pulled in by `@import`, in the order they appear
+    imports: ~[Stylesheet],
+    /// The media this sheet applies to
+    media: MediaList,
+    title: Option<~str>,
+    /// The character encoding the sheet was decoded with
+    encoding: ~str,
+    encoding_source: EncodingSource,
+    /// The sheet as libcss was given it, kept to give it again when the sheet
+    /// is copied or changed. Once decoded, this is the decoded text
+    source: ~[u8],
+    /// The encoding of `source`
+    source_encoding: ~str,
+    /// Whether we could decode the sheet, and so know its rules
+    decoded: bool,
+    /// The statements of the sheet, not counting those of imported sheets
+    rules: ~[Rule],
+    /// Where each of `rules` is in `source`
+    spans: ~[RuleSpan],
+    /// Problems found while parsing, in source order
+    diagnostics: ~[ParseDiagnostic],
+    /// Kept so that rebuilt copies of the sheet resolve system colors too
+    color_resolver: Option<@ColorResolver>
 }
 
 pub impl Stylesheet {
     static fn new(url: Url, input: DataStream) -> Stylesheet {
+        parse_stylesheet(url, input, &StylesheetParams::new())
+    }
+
+    static fn new_with_params(url: Url, input: DataStream, params: &StylesheetParams) -> Stylesheet {
+        parse_stylesheet(url, input, params)
+    }
+
+    /**
+    Parse a stylesheet held in a string. It is always read as UTF-8, the
+    protocol encoding, whatever any `@charset` rule says.
+    */
+    static fn from_str(url: Url, css: &str) -> Stylesheet {
+        parse_stylesheet_str(url, css)
+    }
+
+    /** Parse a stylesheet from bytes in an as yet undetermined encoding */
+    static fn from_bytes(url: Url, bytes: &[u8]) -> Stylesheet {
+        Stylesheet::new(url, bytes_stream(bytes.to_owned()))
+    }
+
+    /**
+    Parse a stylesheet from `reader`, which is read in chunks of
+    `DEFAULT_CHUNK_SIZE` bytes and handed to libcss as they arrive. The
+    whole sheet is still kept in memory, since libcss has to be given it
+    again to copy the sheet or build it for a device.
+    */
+    static fn from_reader(url: Url, reader: @Reader) -> Stylesheet {
+        Stylesheet::from_reader_with_chunk_size(url, reader, DEFAULT_CHUNK_SIZE)
+    }
+
+    static fn from_reader_with_chunk_size(url: Url, reader: @Reader, chunk_size: uint) -> Stylesheet {
+        fail_unless!(chunk_size > 0);
+        Stylesheet::new(url, reader_stream(reader, chunk_size))
+    }
+
+    /**
+    Parse a stylesheet, using `loader` to fetch the contents of any
+    `@import`ed sheets
+    */
+    static fn new_with_loader(url: Url, input: DataStream, loader: @StylesheetLoader) -> Stylesheet {
+        parse_stylesheet(url, input, &StylesheetParams::new().loader(loader))
+    }
+
+    /**
+    Parse the declarations of an element's `style` attribute, e.g.
+    `color: red; display: none`. The result is passed to
+    `SelectCtx::select_style_with_inline`, not appended to a `SelectCtx`.
+    */
+    static fn from_inline_style(base: Url, text: &str) -> Stylesheet {
+        parse_inline_style(base, bytes_stream(str::to_bytes(text)))
+    }
+
+    /** The title given in the `StylesheetParams`, if any */
+    fn title(&self) -> Option<~str> {
+        copy self.title
+    }
+
+    /** The name of the character encoding the sheet was decoded with, e.g. `UTF-8` */
+    fn encoding(&self) -> ~str {
+        copy self.encoding
+    }
+
+    /** How the sheet's character encoding was chosen */
+    fn encoding_source(&self) -> EncodingSource {
+        self.encoding_source
+    }
+
+    /**
+    The problems found while parsing this sheet: declarations, rules and
+    at-rules that were dropped, and constructs that were repaired. Problems
+    in `@import`ed sheets are reported by those sheets.
+    */
+    fn diagnostics(&self) -> &self/[ParseDiagnostic] {
+        let diagnostics: &self/[ParseDiagnostic] = self.diagnostics;
+        diagnostics
+    }
+
+    /**
+    The rules of this sheet, in source order. `@import`ed sheets have their
+    own rules. A sheet made from a style attribute has a single style rule
+    with no selectors. Empty if the sheet is in an encoding we can't
+    decode, which `diagnostics` reports. Some declarations are kept here
+    that libcss drops; see the `rules` module.
+    */
+    fn rules(&self) -> &self/[Rule] {
+        let rules: &self/[Rule] = self.rules;
+        rules
+    }
+
+    /**
+    The fonts the sheet declares with `@font-face`, those of its imports
+    first. Rules inside `@media` blocks are included whatever the media.
+    */
+    fn font_faces(&self) -> ~[FontFaceRule] {
+        let mut font_faces = ~[];
+        for self.imports.each |sheet| {
+            font_faces.push_all(sheet.font_faces());
+        }
+        font_faces.push_all(font_face_rules(self.rules));
+        font_faces
+    }
+
+    /**
+    Write the sheet's rules as normalized CSS, one rule per line. See the
+    `serialize` module for what is normalized.
+    */
+    fn to_css(&self, writer: @Writer) {
+        for self.rules.each |rule| {
+            writer.write_line(rule.to_css());
+        }
+    }
+
+    /** Whether the sheet or its imports have media feature expressions */
+    fn depends_on_device(&self) -> bool {
+        self.media.depends_on_device() || rules_depend_on_device(self.rules) ||
+            self.imports.any(|sheet| sheet.depends_on_device())
+    }
+
+    /**
+    The indices of the rules that apply differently on `new_device` than on
+    `old_device`, i.e. the `@media` rules whose queries match one but not
+    the other. Imported sheets are not looked at.
+    */
+    fn rules_changed_by_device(&self, old_device: &Device, new_device: &Device) -> ~[uint] {
+        rules_changed_by_device(self.rules, old_device, new_device)
+    }
+
+    /** Whether the sheet or its imports apply differently on the two devices */
+    fn changed_by_device(&self, old_device: &Device, new_device: &Device) -> bool {
+        self.media.to_net(old_device) != self.media.to_net(new_device) ||
+            !self.rules_changed_by_device(old_device, new_device).is_empty() ||
+            self.imports.any(|sheet| sheet.changed_by_device(old_device, new_device))
+    }
+
+    /**
+    Parse `text` as a single rule and insert it before the rule at `index`,
+    as CSSOM's `CSSStyleSheet.insertRule` does. Returns `index`.
+
+    Only `text` is parsed into `rules`, but libcss has no way to add a rule
+    to a sheet it has parsed, so its sheet is built again from the whole
+    source. This costs as much as parsing the sheet anew.
+
+    An inserted `@import` rule does not load the sheet it names. A
+    `SelectCtx` holding a copy of this sheet must be given the new version
+    with `SelectCtx::replace_sheet`.
+    */
+    fn insert_rule(&mut self, index: uint, text: &str) -> Result<uint, RuleError> {
+        if !self.decoded {
+            return Err(RuleEncodingUnsupported);
+        }
+        if self.inline_style {
+            return Err(RuleNotAllowed);
+        }
+        if index > self.rules.len() {
+            return Err(RuleIndexOutOfRange);
+        }
+        let (raw_rules, _) = parse_rules(text);
+        if raw_rules.len() != 1 {
+            return Err(RuleSyntaxError);
+        }
+        let raw = &raw_rules[0];
+        let is_import = match raw.at_keyword {
+            Some(~"charset") => return Err(RuleNotAllowed),
+            Some(~"import") => true,
+            _ => false
+        };
+        let rule = match rule_from_raw(raw, &self.url) {
+            Some(rule) => rule,
+            None => return Err(RuleSyntaxError)
+        };
+
+        // @import rules must all come before any other rule
+        let follows_other = index > 0 && !is_import_rule(&self.rules[index - 1]);
+        let precedes_import = index < self.rules.len() && is_import_rule(&self.rules[index]);
+        if (is_import && follows_other) || (!is_import && precedes_import) {
+            return Err(RuleNotAllowed);
+        }
+
+        // Only the new rule's text goes into the source, so the rest of the
+        // sheet reaches libcss exactly as it was written
+        let rule_text = str::to_bytes(text.slice(raw.span.rule.start, raw.span.rule.end) + "\n");
+        let at = if index < self.spans.len() {
+            self.spans[index].rule.start
+        } else {
+            self.source.len()
+        };
+        self.splice_source(Span { start: at, end: at }, rule_text);
+        let span = shift_rule_span(raw.span, at as int - raw.span.rule.start as int);
+        self.rules.insert(index, rule);
+        self.spans.insert(index, span);
+        let inner = rebuild_net_stylesheet(&*self);
+        self.inner = inner;
+        Ok(index)
+    }
+
+    /**
+    Remove the rule at `index`, as CSSOM's `CSSStyleSheet.deleteRule` does.
+    Deleting an `@import` rule also drops the sheet it imported. As with
+    `insert_rule`, libcss parses the rest of the sheet again.
+    */
+    fn delete_rule(&mut self, index: uint) -> Result<(), RuleError> {
+        if !self.decoded {
+            return Err(RuleEncodingUnsupported);
+        }
+        if index >= self.rules.len() {
+            return Err(RuleIndexOutOfRange);
+        }
+        match self.rules.remove(index) {
+            RuleImport(import) => {
+                match self.imports.position(|sheet| sheet.url == import.url) {
+                    Some(i) => { self.imports.remove(i); }
+                    None => ()
+                }
+            }
+            _ => ()
+        }
+        let span = self.spans.remove(index);
+        self.splice_source(span.rule, []);
+        let inner = rebuild_net_stylesheet(&*self);
+        self.inner = inner;
+        Ok(())
+    }
+
+    /**
+    Replace the `span` of the source with `text`, moving the spans of the
+    rules after it to match
+    */
+    priv fn splice_source(&mut self, span: Span, text: &[u8]) {
+        let mut source = vec::with_capacity(self.source.len() + text.len());
+        source.push_all(self.source.slice(0, span.start));
+        source.push_all(text);
+        source.push_all(self.source.slice(span.end, self.source.len()));
+        self.source = source;
+        let by = text.len() as int - (span.end - span.start) as int;
+        for uint::range(0, self.spans.len()) |i| {
+            if self.spans[i].rule.start >= span.end {
+                self.spans[i] = shift_rule_span(self.spans[i], by);
+            }
+        }
+    }
+}
+
+fn shift_span(span: Span, by: int) -> Span {
+    Span {
+        start: (span.start as int + by) as uint,
+        end: (span.end as int + by) as uint
+    }
+}
+
+fn shift_rule_span(span: RuleSpan, by: int) -> RuleSpan {
+    RuleSpan {
+        rule: shift_span(span.rule, by),
+        block: span.block.map(|&block| shift_span(block, by))
+    }
+}
+
+fn is_import_rule(rule: &Rule) -> bool {
+    match *rule {
+        RuleImport(_) => true,
+        _ => false
+    }
+}
+
+/** Copies have their own libcss sheet, which parses the whole source again */
+impl Clone for Stylesheet {
+    fn clone(&self) -> Stylesheet {
         Stylesheet {
-            inner: parse_stylesheet(url, input)
+            inner: rebuild_net_stylesheet(self),
+            url: copy self.url,
+            level: self.level,
+            allow_quirks: self.allow_quirks,
+            inline_style: self.inline_style,
+            imports: self.imports.map(|sheet| sheet.clone()),
+            media: copy self.media,
+            title: copy self.title,
+            encoding: copy self.encoding,
+            encoding_source: self.encoding_source,
+            source: copy self.source,
+            source_encoding: copy self.source_encoding,
+            decoded: self.decoded,
+            rules: copy self.rules,
+            spans: copy self.spans,
+            diagnostics: copy self.diagnostics,
+            color_resolver: self.color_resolver
+        }
+    }
+}
+
+/** Why `insert_rule` or `delete_rule` failed */
+#[deriving_eq]
+pub enum RuleError {
+    /// The index is past the end of the sheet's rules
+    RuleIndexOutOfRange,
+    /// The text isn't exactly one valid rule
+    RuleSyntaxError,
+    /// The rule can't go at that index, e.g. a style rule before an `@import`
+    RuleNotAllowed,
+    /// The sheet is in an encoding we can't decode, so its rules aren't known
+    RuleEncodingUnsupported
+}
+
+/**
+Options controlling how a `Stylesheet` is parsed, built up from
+`StylesheetParams::new()`, e.g.
+
+    StylesheetParams::new().title("Large print").media(MediaList::new(~[MediaPrint]))
+*/
+pub struct StylesheetParams {
+    level: LanguageLevel,
+    /// Whether to accept the quirks of pages rendered in quirks mode
+    allow_quirks: bool,
+    /// The sheet's title, naming the alternate stylesheet set it belongs to
+    title: Option<~str>,
+    /// The media the sheet applies to, e.g. from `<link media="print">`
+    media: MediaList,
+    /// The charset label from the transport layer, e.g. an HTTP `Content-Type` header
+    protocol_encoding: Option<~str>,
+    /// The encoding of the referring document, used if nothing else names one
+    environment_encoding: Option<~str>,
+    /// Fetches `@import`ed sheets. Without one, `@import` rules are ignored
+    loader: Option<@StylesheetLoader>,
+    /// Resolves system colors. Without one, they are invalid
+    color_resolver: Option<@ColorResolver>
+}
+
+pub impl StylesheetParams {
+    static fn new() -> StylesheetParams {
+        StylesheetParams {
+            level: Level21,
+            allow_quirks: false,
+            title: None,
+            media: MediaList::all(),
+            protocol_encoding: None,
+            environment_encoding: None,
+            loader: None,
+            color_resolver: None
+        }
+    }
+
+    fn level(self, level: LanguageLevel) -> StylesheetParams {
+        StylesheetParams { level: level, .. self }
+    }
+
+    fn allow_quirks(self, allow_quirks: bool) -> StylesheetParams {
+        StylesheetParams { allow_quirks: allow_quirks, .. self }
+    }
+
+    fn title(self, title: &str) -> StylesheetParams {
+        StylesheetParams { title: Some(title.to_owned()), .. self }
+    }
+
+    fn media(self, media: MediaList) -> StylesheetParams {
+        StylesheetParams { media: media, .. self }
+    }
+
+    fn protocol_encoding(self, label: &str) -> StylesheetParams {
+        StylesheetParams { protocol_encoding: Some(label.to_owned()), .. self }
+    }
+
+    fn environment_encoding(self, label: &str) -> StylesheetParams {
+        StylesheetParams { environment_encoding: Some(label.to_owned()), .. self }
+    }
+
+    fn loader(self, loader: @StylesheetLoader) -> StylesheetParams {
+        StylesheetParams { loader: Some(loader), .. self }
+    }
+
+    fn color_resolver(self, color_resolver: @ColorResolver) -> StylesheetParams {
+        StylesheetParams { color_resolver: Some(color_resolver), .. self }
+    }
+}
+
+/**
+Fetches the stylesheets referenced by `@import` rules. `url` has already
+been resolved against the importing sheet.
+*/
+pub trait StylesheetLoader {
+    fn load(&self, url: &Url, media: &MediaList) -> Option<DataStream>;
+}
+
+/**
+Maps the CSS2 system colors, such as `ButtonFace` and `WindowText`, to the
+colors of the host's theme. Declarations using a color it doesn't know are
+dropped.
+*/
+pub trait ColorResolver {
+    /// `name` is in lower case, e.g. `buttonface`
+    fn resolve_system_color(&self, name: &str) -> Option<Color>;
+}
+
+/** A `StylesheetLoader` serving sheets from memory, keyed by URL */
+pub struct InMemoryLoader {
+    sheets: ~[(~str, ~str)]
+}
+
+pub impl InMemoryLoader {
+    static fn new() -> InMemoryLoader {
+        InMemoryLoader {
+            sheets: ~[]
+        }
+    }
+
+    fn add(&mut self, url: &str, css: &str) {
+        self.sheets.push((url.to_owned(), css.to_owned()));
+    }
+}
+
+impl StylesheetLoader for InMemoryLoader {
+    fn load(&self, url: &Url, _media: &MediaList) -> Option<DataStream> {
+        let url = url.to_str();
+        for self.sheets.each |&(ref sheet_url, ref css)| {
+            if *sheet_url == url {
+                return Some(bytes_stream(str::to_bytes(*css)));
+            }
         }
+        None
     }
 }
@@ -0,0 +1,1004 @@
+/*!
+The CSS 2.1 core syntax, section 4
+
+libcss does the parsing that feeds the cascade, but it discards anything it
+can't use without saying why. This module tokenizes and parses stylesheets
+far enough to report those problems, with their source positions, following
+the error handling rules of CSS 2.1 section 4.2: anything malformed is
+skipped up to the end of the enclosing declaration or statement, respecting
+matching brackets and quotes, and parsing carries on from there.
+*/
+
+use properties::{is_known_property, is_known_page_property};
+
+/** How serious a problem found while parsing is */
+#[deriving_eq]
+pub enum DiagnosticSeverity {
+    /// Input that breaks the CSS grammar. It has been dropped.
+    SeverityError,
+    /// Well-formed input that is ignored or was repaired
+    SeverityWarning
+}
+
+/** Why a piece of a stylesheet was dropped or repaired */
+#[deriving_eq]
+pub enum DiagnosticReason {
+    UnknownProperty,
+    InvalidValue,
+    /// A closing bracket without an opening one, or a mismatched one
+    UnbalancedBlock,
+    /// A declaration with no colon, or that doesn't start with a property name
+    MalformedDeclaration,
+    InvalidSelector,
+    UnknownAtRule,
+    /// An at-rule in a place it isn't allowed, e.g. `@import` after a style rule
+    MisplacedAtRule,
+    /// A string with a newline in it
+    UnterminatedString,
+    BadUrl,
+    /// The sheet ended inside a comment, string or block, which was closed
+    UnexpectedEndOfInput,
+    /// The sheet is in an encoding, given as the token, that we can't decode
+    /// to look at, so it has no rules
+    UnsupportedEncoding
+}
+
+/** A problem found while parsing a stylesheet */
+#[deriving_eq]
+pub struct ParseDiagnostic {
+    severity: DiagnosticSeverity,
+    /// 1-based line of the offending token
+    line: uint,
+    /// 1-based column of the offending token, in characters
+    column: uint,
+    /// The offending token, as CSS text
+    token: ~str,
+    reason: DiagnosticReason
+}
+
+/** A token of the CSS 2.1 tokenizer, section 4.1.1 */
+#[deriving_eq]
+pub enum Token {
+    Ident(~str),
+    AtKeyword(~str),
+    QuotedString(~str),
+    BadString,
+    Hash(~str),
+    Number(float),
+    Percentage(float),
+    Dimension(float, ~str),
+    Uri(~str),
+    BadUri,
+    UnicodeRange(~str),
+    CDO,
+    CDC,
+    Colon,
+    Semicolon,
+    LeftBrace,
+    RightBrace,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Whitespace,
+    /// An identifier immediately followed by `(`
+    Function(~str),
+    Includes,
+    DashMatch,
+    PrefixMatch,
+    SuffixMatch,
+    SubstringMatch,
+    Delim(char)
+}
+
+/** A token and where it starts in the source */
+#[deriving_eq]
+pub struct SourceToken {
+    token: Token,
+    line: uint,
+    column: uint,
+    span: Span
+}
+
+/** A run of the source, as byte offsets */
+#[deriving_eq]
+pub struct Span {
+    start: uint,
+    end: uint
+}
+
+/** Where a rule is in the source, and the contents of its `{}` block if it has one */
+#[deriving_eq]
+pub struct RuleSpan {
+    rule: Span,
+    block: Option<Span>
+}
+
+/** A `property: value` pair from a declaration block */
+pub struct RawDeclaration {
+    /// The property name, lower-cased
+    name: ~str,
+    /// The value, without surrounding whitespace or a trailing `!important`
+    value: ~[SourceToken],
+    important: bool,
+    line: uint,
+    column: uint
+}
+
+/**
+A statement of a stylesheet. Style rules have no `at_keyword`. Style rules,
+`@font-face` and `@page` have `declarations`; `@media` has nested `rules`.
+*/
+pub struct RawRule {
+    /// The at-keyword, lower-cased and without the `@`
+    at_keyword: Option<~str>,
+    /// Everything between the selector or at-keyword and the block or `;`
+    prelude: ~[SourceToken],
+    declarations: ~[RawDeclaration],
+    rules: ~[RawRule],
+    line: uint,
+    column: uint,
+    span: RuleSpan
+}
+
+/** Descriptors allowed in `@font-face`, CSS Fonts Level 3 section 4 */
+const FONT_FACE_DESCRIPTORS: &'static [&'static str] = &[
+    "font-family", "src", "font-style", "font-weight", "font-stretch", "font-variant",
+    "unicode-range", "font-feature-settings"
+];
+
+/**
+Parse a whole stylesheet, returning its statements along with any problems
+found. Statements that are malformed or ignored by CSS 2.1 are left out.
+*/
+pub fn parse_rules(input: &str) -> (~[RawRule], ~[ParseDiagnostic]) {
+    let mut parser = Parser::new(tokenize(input));
+    let rules = parser.parse_rule_list(true);
+    (rules, parser.diagnostics)
+}
+
+/** Parse the contents of a `style` attribute, a bare declaration list */
+pub fn parse_inline_declarations(input: &str) -> (~[RawDeclaration], ~[ParseDiagnostic]) {
+    let mut parser = Parser::new(tokenize(input));
+    let declarations = parser.parse_declaration_list(is_known_property);
+    (declarations, parser.diagnostics)
+}
+
+struct Parser {
+    tokens: ~[SourceToken],
+    pos: uint,
+    diagnostics: ~[ParseDiagnostic]
+}
+
+priv impl Parser {
+    static fn new(tokens: ~[SourceToken]) -> Parser {
+        Parser {
+            tokens: tokens,
+            pos: 0,
+            diagnostics: ~[]
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<Token> {
+        if self.at_end() {
+            None
+        } else {
+            Some(copy self.tokens[self.pos].token)
+        }
+    }
+
+    fn next(&mut self) -> Option<SourceToken> {
+        if self.at_end() {
+            None
+        } else {
+            self.pos += 1;
+            Some(copy self.tokens[self.pos - 1])
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek() == Some(Whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn report(&mut self, severity: DiagnosticSeverity, at: &SourceToken, reason: DiagnosticReason) {
+        self.diagnostics.push(ParseDiagnostic {
+            severity: severity,
+            line: at.line,
+            column: at.column,
+            token: at.token.to_str(),
+            reason: reason
+        });
+    }
+
+    /** Report problems with tokens the tokenizer had to repair */
+    fn check_token(&mut self, token: &SourceToken) {
+        match token.token {
+            BadString => self.report(SeverityError, token, UnterminatedString),
+            BadUri => self.report(SeverityError, token, BadUrl),
+            _ => ()
+        }
+    }
+
+    /**
+    Read tokens up to, but not including, a `stop` token outside any
+    brackets, or the end of input. Returns `true` if any brackets were
+    mismatched or any bad strings or urls were found. Those problems are
+    reported unless `quiet` is set, for contents that will be parsed again.
+    */
+    fn consume_until(&mut self, stop: &fn(&Token) -> bool, quiet: bool,
+                     out: &mut ~[SourceToken]) -> bool {
+        let mut closers = ~[];
+        let mut malformed = false;
+        loop {
+            let token = match self.peek() {
+                None => break,
+                Some(token) => token
+            };
+            if closers.is_empty() && stop(&token) {
+                break;
+            }
+            let source_token = self.next().get();
+            match token {
+                LeftBrace => closers.push(RightBrace),
+                LeftParen | Function(*) => closers.push(RightParen),
+                LeftBracket => closers.push(RightBracket),
+                RightBrace | RightParen | RightBracket => {
+                    if !closers.is_empty() && *closers.last() == token {
+                        closers.pop();
+                    } else {
+                        if !quiet {
+                            self.report(SeverityError, &source_token, UnbalancedBlock);
+                        }
+                        malformed = true;
+                    }
+                }
+                BadString | BadUri => {
+                    if !quiet {
+                        self.check_token(&source_token);
+                    }
+                    malformed = true;
+                }
+                _ => ()
+            }
+            out.push(source_token);
+        }
+        if !closers.is_empty() && !quiet {
+            let last = copy self.tokens[self.tokens.len() - 1];
+            self.report(SeverityWarning, &last, UnexpectedEndOfInput);
+        }
+        malformed
+    }
+
+    /**
+    Read the contents of a `{}` block; the `{` has already been read. Returns
+    them along with where they are in the source.
+    */
+    fn consume_block(&mut self, open: &SourceToken) -> (~[SourceToken], Span) {
+        let mut contents = ~[];
+        self.consume_until(|t| *t == RightBrace, true, &mut contents);
+        let end = match self.next() {
+            Some(close) => close.span.start,
+            None => {
+                self.report(SeverityWarning, open, UnexpectedEndOfInput);
+                contents.last_opt().map_default(open.span.end, |last| last.span.end)
+            }
+        };
+        (contents, Span { start: open.span.end, end: end })
+    }
+
+    /** Where the rule starting with `start` is, now that it has been read */
+    fn rule_span(&self, start: &SourceToken, block: Option<Span>) -> RuleSpan {
+        RuleSpan {
+            rule: Span { start: start.span.start, end: self.tokens[self.pos - 1].span.end },
+            block: block
+        }
+    }
+
+    fn parse_rule_list(&mut self, top_level: bool) -> ~[RawRule] {
+        let mut rules = ~[];
+        // @charset must be the very first thing, and @import must precede
+        // everything but @charset
+        let mut first = true;
+        let mut imports_allowed = true;
+
+        loop {
+            let token = match self.peek() {
+                None => break,
+                Some(token) => token
+            };
+            match token {
+                Whitespace | CDO | CDC => {
+                    self.pos += 1;
+                    first = false;
+                    loop;
+                }
+                AtKeyword(name) => {
+                    let start = copy self.tokens[self.pos];
+                    let name = name.to_lower();
+                    match self.parse_at_rule() {
+                        Some(rule) => {
+                            let allowed = match name {
+                                ~"charset" => top_level && first,
+                                ~"import" => top_level && imports_allowed,
+                                ~"media" => top_level,
+                                ~"page" | ~"font-face" => true,
+                                _ => {
+                                    self.report(SeverityWarning, &start, UnknownAtRule);
+                                    first = false;
+                                    loop;
+                                }
+                            };
+                            if !allowed {
+                                self.report(SeverityWarning, &start, MisplacedAtRule);
+                            } else {
+                                if name != ~"charset" && name != ~"import" {
+                                    imports_allowed = false;
+                                }
+                                rules.push(rule);
+                            }
+                        }
+                        None => ()
+                    }
+                }
+                _ => {
+                    imports_allowed = false;
+                    match self.parse_style_rule() {
+                        Some(rule) => rules.push(rule),
+                        None => ()
+                    }
+                }
+            }
+            first = false;
+        }
+
+        rules
+    }
+
+    fn parse_at_rule(&mut self) -> Option<RawRule> {
+        let start = self.next().get();
+        let name = match start.token {
+            AtKeyword(ref name) => name.to_lower(),
+            _ => fail!(~"expected an at-keyword")
+        };
+
+        let mut prelude = ~[];
+        let malformed = self.consume_until(|t| *t == Semicolon || *t == LeftBrace, false,
+                                           &mut prelude);
+        let block = match self.next() {
+            Some(open) => {
+                if open.token == LeftBrace {
+                    Some(self.consume_block(&open))
+                } else {
+                    None
+                }
+            }
+            None => None
+        };
+        if malformed {
+            return None;
+        }
+
+        let mut rule = RawRule {
+            at_keyword: Some(copy name),
+            prelude: trim_whitespace(prelude),
+            declarations: ~[],
+            rules: ~[],
+            line: start.line,
+            column: start.column,
+            span: self.rule_span(&start, block.map(|&(_, span)| span))
+        };
+        match block {
+            Some((block, _)) => {
+                // The blocks of unknown at-rules are ignored unseen
+                let mut inner = Parser::new(block);
+                match name {
+                    ~"media" => rule.rules = inner.parse_rule_list(false),
+                    ~"font-face" => {
+                        rule.declarations = inner.parse_declaration_list(is_font_face_descriptor)
+                    }
+                    ~"page" => {
+                        rule.declarations = inner.parse_declaration_list(is_known_page_property)
+                    }
+                    _ => ()
+                }
+                self.diagnostics.push_all(inner.diagnostics);
+            }
+            None => ()
+        }
+        Some(rule)
+    }
+
+    fn parse_style_rule(&mut self) -> Option<RawRule> {
+        let start = copy self.tokens[self.pos];
+        let mut prelude = ~[];
+        let mut malformed = self.consume_until(|t| *t == LeftBrace, false, &mut prelude);
+        let prelude = trim_whitespace(prelude);
+
+        let open = match self.next() {
+            Some(open) => open,
+            None => {
+                // A selector with no declaration block
+                self.report(SeverityError, &start, InvalidSelector);
+                return None;
+            }
+        };
+        if prelude.is_empty() {
+            self.report(SeverityError, &open, InvalidSelector);
+            malformed = true;
+        }
+
+        let (block, block_span) = self.consume_block(&open);
+        let mut inner = Parser::new(block);
+        let declarations = inner.parse_declaration_list(is_known_property);
+        self.diagnostics.push_all(inner.diagnostics);
+        if malformed {
+            return None;
+        }
+
+        Some(RawRule {
+            at_keyword: None,
+            prelude: prelude,
+            declarations: declarations,
+            rules: ~[],
+            line: start.line,
+            column: start.column,
+            span: self.rule_span(&start, Some(block_span))
+        })
+    }
+
+    fn parse_declaration_list(&mut self, is_known: &fn(&str) -> bool) -> ~[RawDeclaration] {
+        let mut declarations = ~[];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(Semicolon) => {
+                    self.pos += 1;
+                }
+                Some(_) => {
+                    match self.parse_declaration(is_known) {
+                        Some(declaration) => declarations.push(declaration),
+                        None => ()
+                    }
+                }
+            }
+        }
+        declarations
+    }
+
+    fn parse_declaration(&mut self, is_known: &fn(&str) -> bool) -> Option<RawDeclaration> {
+        let start = copy self.tokens[self.pos];
+        let name = match start.token {
+            Ident(ref name) => name.to_lower(),
+            _ => {
+                self.report(SeverityError, &start, MalformedDeclaration);
+                self.skip_declaration();
+                return None;
+            }
+        };
+        self.pos += 1;
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(Colon) => self.pos += 1,
+            Some(_) => {
+                let unexpected = copy self.tokens[self.pos];
+                self.report(SeverityError, &unexpected, MalformedDeclaration);
+                self.skip_declaration();
+                return None;
+            }
+            None => {
+                self.report(SeverityError, &start, MalformedDeclaration);
+                return None;
+            }
+        }
+
+        let mut value = ~[];
+        let malformed = self.consume_until(|t| *t == Semicolon, false, &mut value);
+        let mut value = trim_whitespace(value);
+        let important = strip_important(&mut value);
+
+        if malformed {
+            return None;
+        }
+        if !is_known(name) {
+            self.report(SeverityWarning, &start, UnknownProperty);
+            return None;
+        }
+        if value.is_empty() {
+            self.report(SeverityError, &start, InvalidValue);
+            return None;
+        }
+        for value.each |token| {
+            match token.token {
+                // No CSS 2.1 property takes a block, and a '!' may only
+                // introduce '!important'
+                LeftBrace | Delim('!') | AtKeyword(*) => {
+                    self.report(SeverityError, token, InvalidValue);
+                    return None;
+                }
+                _ => ()
+            }
+        }
+
+        Some(RawDeclaration {
+            name: name,
+            value: value,
+            important: important,
+            line: start.line,
+            column: start.column
+        })
+    }
+
+    /** Skip to the end of a malformed declaration */
+    fn skip_declaration(&mut self) {
+        let mut skipped = ~[];
+        self.consume_until(|t| *t == Semicolon, true, &mut skipped);
+    }
+}
+
+/** Remove a trailing `! important` from a declaration value */
+fn strip_important(value: &mut ~[SourceToken]) -> bool {
+    let len = value.len();
+    if len < 2 {
+        return false;
+    }
+    let is_important = match value[len - 1].token {
+        Ident(ref ident) => ident.to_lower() == ~"important",
+        _ => false
+    };
+    if !is_important {
+        return false;
+    }
+    let mut bang = len - 2;
+    while bang > 0 && value[bang].token == Whitespace {
+        bang -= 1;
+    }
+    if value[bang].token != Delim('!') {
+        return false;
+    }
+    let trimmed = trim_whitespace(value.slice(0, bang).to_owned());
+    *value = trimmed;
+    true
+}
+
+fn trim_whitespace(tokens: ~[SourceToken]) -> ~[SourceToken] {
+    let mut start = 0;
+    let mut end = tokens.len();
+    while start < end && tokens[start].token == Whitespace {
+        start += 1;
+    }
+    while end > start && tokens[end - 1].token == Whitespace {
+        end -= 1;
+    }
+    tokens.slice(start, end).to_owned()
+}
+
+fn is_font_face_descriptor(name: &str) -> bool {
+    FONT_FACE_DESCRIPTORS.any(|descriptor| str::eq_slice(*descriptor, name))
+}
+
+/** Split CSS text into tokens, dropping comments */
+pub fn tokenize(input: &str) -> ~[SourceToken] {
+    let mut tokenizer = Tokenizer {
+        input: str::chars(input),
+        pos: 0,
+        offset: 0,
+        line: 1,
+        column: 1
+    };
+    let mut tokens = ~[];
+    loop {
+        match tokenizer.next_token() {
+            Some(token) => tokens.push(token),
+            None => break
+        }
+    }
+    tokens
+}
+
+struct Tokenizer {
+    input: ~[char],
+    pos: uint,
+    /// Where `pos` is in the source, in bytes
+    offset: uint,
+    line: uint,
+    column: uint
+}
+
+priv impl Tokenizer {
+    fn char_at(&self, offset: uint) -> Option<char> {
+        if self.pos + offset < self.input.len() {
+            Some(self.input[self.pos + offset])
+        } else {
+            None
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        let mut i = 0;
+        for str::each_char(s) |c| {
+            match self.char_at(i) {
+                Some(d) if d == c => (),
+                _ => return false
+            }
+            i += 1;
+        }
+        true
+    }
+
+    fn advance(&mut self, count: uint) {
+        for count.times {
+            if self.pos >= self.input.len() {
+                break;
+            }
+            match self.input[self.pos] {
+                '\n' | '\x0c' => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                // \r\n counts as a single newline
+                '\r' if self.char_at(1) != Some('\n') => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                '\r' => (),
+                _ => self.column += 1
+            }
+            self.offset += utf8_len(self.input[self.pos]);
+            self.pos += 1;
+        }
+    }
+
+    fn next_char(&mut self) -> char {
+        let c = self.input[self.pos];
+        self.advance(1);
+        c
+    }
+
+    fn next_token(&mut self) -> Option<SourceToken> {
+        // Comments aren't tokens
+        while self.starts_with("/*") {
+            self.advance(2);
+            while self.pos < self.input.len() && !self.starts_with("*/") {
+                self.advance(1);
+            }
+            self.advance(2);
+        }
+
+        let c = match self.char_at(0) {
+            None => return None,
+            Some(c) => c
+        };
+        let line = self.line;
+        let column = self.column;
+        let start = self.offset;
+
+        let token = if is_whitespace(c) {
+            while self.char_at(0).map_default(false, |c| is_whitespace(*c)) {
+                self.advance(1);
+            }
+            Whitespace
+        } else if c == '"' || c == '\'' {
+            self.consume_string()
+        } else if self.starts_with_number() {
+            self.consume_numeric()
+        } else if self.starts_with("<!--") {
+            self.advance(4);
+            CDO
+        } else if self.starts_with("-->") {
+            self.advance(3);
+            CDC
+        } else if (c == 'u' || c == 'U') && self.char_at(1) == Some('+') &&
+                  self.char_at(2).map_default(false, |c| is_hex_digit(*c) || *c == '?') {
+            self.consume_unicode_range()
+        } else if self.starts_with_ident(0) {
+            self.consume_ident_like()
+        } else if c == '@' && self.starts_with_ident(1) {
+            self.advance(1);
+            AtKeyword(self.consume_name())
+        } else if c == '#' && self.char_at(1).map_default(false, |c| is_name_char(*c)) {
+            self.advance(1);
+            Hash(self.consume_name())
+        } else if self.char_at(1) == Some('=') && str::contains_char("~|^$*", c) {
+            self.advance(2);
+            match c {
+                '~' => Includes,
+                '|' => DashMatch,
+                '^' => PrefixMatch,
+                '$' => SuffixMatch,
+                _ => SubstringMatch
+            }
+        } else {
+            self.advance(1);
+            match c {
+                ':' => Colon,
+                ';' => Semicolon,
+                '{' => LeftBrace,
+                '}' => RightBrace,
+                '(' => LeftParen,
+                ')' => RightParen,
+                '[' => LeftBracket,
+                ']' => RightBracket,
+                c => Delim(c)
+            }
+        };
+
+        Some(SourceToken {
+            token: token,
+            line: line,
+            column: column,
+            span: Span { start: start, end: self.offset }
+        })
+    }
+
+    fn starts_with_ident(&self, offset: uint) -> bool {
+        match self.char_at(offset) {
+            Some('-') => match self.char_at(offset + 1) {
+                Some('\\') => self.char_at(offset + 2).map_default(false, |c| !is_newline(*c)),
+                Some(c) => is_name_start(c),
+                None => false
+            },
+            Some('\\') => self.char_at(offset + 1).map_default(false, |c| !is_newline(*c)),
+            Some(c) => is_name_start(c),
+            None => false
+        }
+    }
+
+    fn starts_with_number(&self) -> bool {
+        let offset = match self.char_at(0) {
+            Some('+') | Some('-') => 1,
+            _ => 0
+        };
+        match self.char_at(offset) {
+            Some('.') => self.char_at(offset + 1).map_default(false, |c| is_digit(*c)),
+            Some(c) => is_digit(c),
+            None => false
+        }
+    }
+
+    /** Consume an escape; the backslash has already been consumed */
+    fn consume_escape(&mut self) -> char {
+        let mut hex = ~"";
+        while hex.len() < 6 && self.char_at(0).map_default(false, |c| is_hex_digit(*c)) {
+            str::push_char(&mut hex, self.next_char());
+        }
+        if hex.is_empty() {
+            return match self.char_at(0) {
+                Some(_) => self.next_char(),
+                None => '\uFFFD'
+            };
+        }
+        // A single whitespace character ends a hex escape
+        if self.starts_with("\r\n") {
+            self.advance(2);
+        } else if self.char_at(0).map_default(false, |c| is_whitespace(*c)) {
+            self.advance(1);
+        }
+        match uint::from_str_radix(hex, 16) {
+            Some(0) | None => '\uFFFD',
+            Some(code) if code > 0x10FFFF || (code >= 0xD800 && code <= 0xDFFF) => '\uFFFD',
+            Some(code) => code as char
+        }
+    }
+
+    fn consume_name(&mut self) -> ~str {
+        let mut name = ~"";
+        loop {
+            match self.char_at(0) {
+                Some('\\') if self.char_at(1).map_default(false, |c| !is_newline(*c)) => {
+                    self.advance(1);
+                    str::push_char(&mut name, self.consume_escape());
+                }
+                Some(c) if is_name_char(c) => {
+                    str::push_char(&mut name, self.next_char());
+                }
+                _ => break
+            }
+        }
+        name
+    }
+
+    fn consume_string(&mut self) -> Token {
+        let quote = self.next_char();
+        let mut value = ~"";
+        loop {
+            match self.char_at(0) {
+                None => {
+                    // CSS 2.1 4.2: close strings at the end of the sheet
+                    return QuotedString(value);
+                }
+                Some(c) if c == quote => {
+                    self.advance(1);
+                    return QuotedString(value);
+                }
+                Some(c) if is_newline(c) => {
+                    // The newline isn't part of the bad string
+                    return BadString;
+                }
+                Some('\\') => {
+                    self.advance(1);
+                    match self.char_at(0) {
+                        None => (),
+                        Some(c) if is_newline(c) => {
+                            // An escaped newline continues the string
+                            if self.starts_with("\r\n") {
+                                self.advance(2);
+                            } else {
+                                self.advance(1);
+                            }
+                        }
+                        Some(_) => str::push_char(&mut value, self.consume_escape())
+                    }
+                }
+                Some(_) => str::push_char(&mut value, self.next_char())
+            }
+        }
+    }
+
+    fn consume_number(&mut self) -> float {
+        let mut repr = ~"";
+        if self.char_at(0) == Some('+') || self.char_at(0) == Some('-') {
+            str::push_char(&mut repr, self.next_char());
+        }
+        while self.char_at(0).map_default(false, |c| is_digit(*c)) {
+            str::push_char(&mut repr, self.next_char());
+        }
+        if self.char_at(0) == Some('.') && self.char_at(1).map_default(false, |c| is_digit(*c)) {
+            str::push_char(&mut repr, self.next_char());
+            while self.char_at(0).map_default(false, |c| is_digit(*c)) {
+                str::push_char(&mut repr, self.next_char());
+            }
+        }
+        float::from_str(repr).get_or_default(0.0)
+    }
+
+    fn consume_numeric(&mut self) -> Token {
+        let value = self.consume_number();
+        if self.char_at(0) == Some('%') {
+            self.advance(1);
+            Percentage(value)
+        } else if self.starts_with_ident(0) {
+            Dimension(value, self.consume_name())
+        } else {
+            Number(value)
+        }
+    }
+
+    fn consume_unicode_range(&mut self) -> Token {
+        let mut range = ~"U+";
+        self.advance(2);
+        let mut digits = 0;
+        while digits < 6 && self.char_at(0).map_default(false, |c| is_hex_digit(*c) || *c == '?') {
+            str::push_char(&mut range, to_ascii_upper(self.next_char()));
+            digits += 1;
+        }
+        if self.char_at(0) == Some('-') && self.char_at(1).map_default(false, |c| is_hex_digit(*c)) {
+            str::push_char(&mut range, self.next_char());
+            digits = 0;
+            while digits < 6 && self.char_at(0).map_default(false, |c| is_hex_digit(*c)) {
+                str::push_char(&mut range, to_ascii_upper(self.next_char()));
+                digits += 1;
+            }
+        }
+        UnicodeRange(range)
+    }
+
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.char_at(0) != Some('(') {
+            return Ident(name);
+        }
+        self.advance(1);
+        if name.to_lower() == ~"url" {
+            self.consume_url()
+        } else {
+            Function(name)
+        }
+    }
+
+    /** Consume the rest of a `url(`, which has already been consumed */
+    fn consume_url(&mut self) -> Token {
+        while self.char_at(0).map_default(false, |c| is_whitespace(*c)) {
+            self.advance(1);
+        }
+        let url = match self.char_at(0) {
+            Some('"') | Some('\'') => match self.consume_string() {
+                QuotedString(url) => Some(url),
+                _ => None
+            },
+            _ => {
+                let mut url = ~"";
+                let mut valid = true;
+                loop {
+                    match self.char_at(0) {
+                        None => break,
+                        Some(')') => break,
+                        Some(c) if is_whitespace(c) => break,
+                        Some('\\') if self.char_at(1).map_default(false, |c| !is_newline(*c)) => {
+                            self.advance(1);
+                            str::push_char(&mut url, self.consume_escape());
+                        }
+                        Some(c) => {
+                            if c == '"' || c == '\'' || c == '(' || c == '\\' || c < ' ' {
+                                valid = false;
+                            }
+                            str::push_char(&mut url, self.next_char());
+                        }
+                    }
+                }
+                if valid { Some(url) } else { None }
+            }
+        };
+        while self.char_at(0).map_default(false, |c| is_whitespace(*c)) {
+            self.advance(1);
+        }
+        match (url, self.char_at(0)) {
+            (Some(url), Some(')')) => {
+                self.advance(1);
+                Uri(url)
+            }
+            // CSS 2.1 4.2: close the url at the end of the sheet
+            (Some(url), None) => Uri(url),
+            _ => {
+                // Skip the rest of the bad url
+                loop {
+                    match self.char_at(0) {
+                        None => break,
+                        Some(')') => {
+                            self.advance(1);
+                            break;
+                        }
+                        Some('\\') => self.advance(2),
+                        Some(_) => self.advance(1)
+                    }
+                }
+                BadUri
+            }
+        }
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || is_newline(c)
+}
+
+pub fn is_newline(c: char) -> bool {
+    c == '\n' || c == '\r' || c == '\x0c'
+}
+
+pub fn is_digit(c: char) -> bool {
+    c >= '0' && c <= '9'
+}
+
+fn is_hex_digit(c: char) -> bool {
+    is_digit(c) || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F')
+}
+
+pub fn is_name_start(c: char) -> bool {
+    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_' || c >= '\x80'
+}
+
+pub fn is_name_char(c: char) -> bool {
+    is_name_start(c) || is_digit(c) || c == '-'
+}
+
+/** How many bytes `c` takes up in UTF-8 */
+fn utf8_len(c: char) -> uint {
+    let c = c as uint;
+    if c < 0x80 { 1 } else if c < 0x800 { 2 } else if c < 0x10000 { 3 } else { 4 }
+}
+
+fn to_ascii_upper(c: char) -> char {
+    if c >= 'a' && c <= 'z' {
+        ((c as u8) - ('a' as u8) + ('A' as u8)) as char
+    } else {
+        c
+    }
+}
@@ -1,4 +1,5 @@
 use n;
+use media_queries::{Device, MediaQuery};
 
 pub enum StylesheetOrigin {
     OriginUA,
@@ -14,4 +15,147 @@ impl StylesheetOrigin {
             OriginAuthor => n::ll::t::CSS_ORIGIN_AUTHOR
         }
     }
-}
\ No newline at end of file
+}
+
+/** The version of the CSS specification to parse stylesheets against */
+#[deriving_eq]
+pub enum LanguageLevel {
+    Level1,
+    Level2,
+    Level21,
+    Level3
+}
+
+impl LanguageLevel {
+    fn to_net(&self) -> n::t::CssLanguageLevel {
+        match *self {
+            Level1 => n::t::CssLevel1,
+            Level2 => n::t::CssLevel2,
+            Level21 => n::t::CssLevel21,
+            Level3 => n::t::CssLevel3
+        }
+    }
+}
+
+/** CSS 2.1 media types, section 7.3 */
+#[deriving_eq]
+pub enum MediaType {
+    MediaAll,
+    MediaAural,
+    MediaBraille,
+    MediaEmbossed,
+    MediaHandheld,
+    MediaPrint,
+    MediaProjection,
+    MediaScreen,
+    MediaSpeech,
+    MediaTty,
+    MediaTv
+}
+
+impl MediaType {
+    /** Look up a media type by its case-insensitive name */
+    static fn from_name(name: &str) -> Option<MediaType> {
+        match name.to_lower() {
+            ~"all" => Some(MediaAll),
+            ~"aural" => Some(MediaAural),
+            ~"braille" => Some(MediaBraille),
+            ~"embossed" => Some(MediaEmbossed),
+            ~"handheld" => Some(MediaHandheld),
+            ~"print" => Some(MediaPrint),
+            ~"projection" => Some(MediaProjection),
+            ~"screen" => Some(MediaScreen),
+            ~"speech" => Some(MediaSpeech),
+            ~"tty" => Some(MediaTty),
+            ~"tv" => Some(MediaTv),
+            _ => None
+        }
+    }
+
+    fn to_net(&self) -> u64 {
+        let media = match *self {
+            MediaAll => n::ll::t::CSS_MEDIA_ALL,
+            MediaAural => n::ll::t::CSS_MEDIA_AURAL,
+            MediaBraille => n::ll::t::CSS_MEDIA_BRAILLE,
+            MediaEmbossed => n::ll::t::CSS_MEDIA_EMBOSSED,
+            MediaHandheld => n::ll::t::CSS_MEDIA_HANDHELD,
+            MediaPrint => n::ll::t::CSS_MEDIA_PRINT,
+            MediaProjection => n::ll::t::CSS_MEDIA_PROJECTION,
+            MediaScreen => n::ll::t::CSS_MEDIA_SCREEN,
+            MediaSpeech => n::ll::t::CSS_MEDIA_SPEECH,
+            MediaTty => n::ll::t::CSS_MEDIA_TTY,
+            MediaTv => n::ll::t::CSS_MEDIA_TV
+        };
+        media as u64
+    }
+}
+
+/**
+The media a stylesheet or rule applies to: a list of media queries, any of
+which may match
+*/
+#[deriving_eq]
+pub struct MediaList {
+    queries: ~[MediaQuery]
+}
+
+pub impl MediaList {
+    static fn all() -> MediaList {
+        MediaList::new(~[MediaAll])
+    }
+
+    static fn new(media: ~[MediaType]) -> MediaList {
+        MediaList {
+            queries: media.map(|m| MediaQuery::new(*m))
+        }
+    }
+
+    static fn from_queries(queries: ~[MediaQuery]) -> MediaList {
+        MediaList {
+            queries: queries
+        }
+    }
+
+    /** Convert from the bitmask libcss uses to describe media lists */
+    static fn from_net(bits: u64) -> MediaList {
+        if bits & MediaAll.to_net() == MediaAll.to_net() {
+            return MediaList::all();
+        }
+        let candidates = ~[MediaAural, MediaBraille, MediaEmbossed, MediaHandheld, MediaPrint,
+                           MediaProjection, MediaScreen, MediaSpeech, MediaTty, MediaTv];
+        MediaList::new(candidates.filtered(|m| bits & m.to_net() != 0))
+    }
+
+    /** The libcss media types the list matches on `device` */
+    fn to_net(&self, device: &Device) -> u64 {
+        let mut bits = 0;
+        for self.queries.each |query| {
+            bits |= query.to_net(device);
+        }
+        bits
+    }
+
+    /** Whether any query has a feature expression, e.g. `(min-width: 600px)` */
+    fn depends_on_device(&self) -> bool {
+        self.queries.any(|query| !query.expressions.is_empty())
+    }
+}
+
+/** The step of encoding detection that chose a stylesheet's character encoding */
+#[deriving_eq]
+pub enum EncodingSource {
+    EncodingFromBom,
+    EncodingFromCharsetRule,
+    EncodingFromProtocol,
+    EncodingFromEnvironment,
+    EncodingDefault
+}
+
+/** The pseudo-elements of CSS 2.1, section 5.12 */
+#[deriving_eq]
+pub enum PseudoElement {
+    PseudoElementFirstLine,
+    PseudoElementFirstLetter,
+    PseudoElementBefore,
+    PseudoElementAfter
+}
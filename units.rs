@@ -5,6 +5,7 @@ Units used by CSS
 #[deriving_eq]
 pub enum Length {
     Em(float), // normalized to 'em'
+    Ex(float), // normalized to 'ex'
     Px(float), // normalized to 'px'
     Pt(float)
 }
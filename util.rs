@@ -1,5 +1,32 @@
+use core::cell::Cell;
+use core::io::{Reader, ReaderUtil};
+
 pub type DataStream = @fn() -> Option<~[u8]>;
 
 pub use netsurfcss::util::VoidPtrLike;
 
+/** A `DataStream` that yields `bytes` in a single chunk */
+pub fn bytes_stream(bytes: ~[u8]) -> DataStream {
+    let bytes = Cell(bytes);
+    let d: DataStream = || {
+        if !bytes.is_empty() {
+            Some(bytes.take())
+        } else {
+            None
+        }
+    };
+    return d;
+}
 
+/** A `DataStream` that reads `reader` to the end, `chunk_size` bytes at a time */
+pub fn reader_stream(reader: @Reader, chunk_size: uint) -> DataStream {
+    let d: DataStream = || {
+        if reader.eof() {
+            None
+        } else {
+            let chunk = reader.read_bytes(chunk_size);
+            if chunk.is_empty() { None } else { Some(chunk) }
+        }
+    };
+    return d;
+}
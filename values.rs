@@ -37,6 +37,7 @@ pub enum CSSMargin {
     CSSMarginAuto
 }
 
+#[deriving_eq]
 pub enum CSSPadding {
     CSSPaddingLength(Length),
     CSSPaddingPercentage(float)
@@ -50,11 +51,13 @@ pub enum CSSBorderWidth {
     CSSBorderWidthLength(Length)
 }
 
+#[deriving_eq]
 pub enum CSSBorderColor {
     CSSBorderColorColor(Color),
     CSSBorderColorTransparent
 }
 
+#[deriving_eq]
 pub enum CSSBorderStyle {
     CSSBorderStyleNone,
     CSSBorderStyleHidden,
@@ -97,24 +100,28 @@ pub enum CSSPosition {
     CSSPositionFixed
 }
 
+#[deriving_eq]
 pub enum CSSTop {
     CSSTopLength(Length),
     CSSTopPercentage,
     CSSTopAuto
 }
 
+#[deriving_eq]
 pub enum CSSRight {
     CSSRightLength(Length),
     CSSRightPercentage(float),
     CSSRightAuto
 }
 
+#[deriving_eq]
 pub enum CSSBottom {
     CSSBottomLength(Length),
     CSSBottomPercentage(float),
     CSSBottomAuto
 }
 
+#[deriving_eq]
 pub enum CSSLeft {
     CSSLeftLength(Length),
     CSSLeftPercentage(float),
@@ -128,6 +135,7 @@ pub enum CSSFloat {
     CSSFloatNone
 }
 
+#[deriving_eq]
 pub enum CSSDirection {
     CSSDirectionLtr,
     CSSDirectionRtl
@@ -157,6 +165,7 @@ pub enum CSSLineHeight {
     CSSLineHeightPercentage(float),
 }
 
+#[deriving_eq]
 pub enum CSSVerticalAlign {
     CSSVerticalAlignBaseline,
     CSSVerticalAlignSub,
@@ -172,6 +181,7 @@ pub enum CSSVerticalAlign {
 
 // CSS 2.1, Section 11 - Visual effects
 
+#[deriving_eq]
 pub enum CSSOverflow {
     CSSOverflowVisible,
     CSSOverflowHidden,
@@ -179,6 +189,7 @@ pub enum CSSOverflow {
     CSSOverflowAuto
 }
 
+#[deriving_eq]
 pub enum CSSVisibility {
     CSSVisibilityVisible,
     CSSVisibilityHidden,
@@ -187,8 +198,40 @@ pub enum CSSVisibility {
 
 // CSS 2.1, Section 12 - Generated content, automatic numbering, and lists
 
+#[deriving_eq]
+pub enum CSSQuotes {
+    CSSQuotesNone,
+    /// Open and close quotes, outermost first
+    CSSQuotesPairs(~[(~str, ~str)])
+}
+
 // CSS 2.1, Section 13 - Paged media
 
+#[deriving_eq]
+pub enum CSSPageBreak {
+    CSSPageBreakAuto,
+    CSSPageBreakAlways,
+    CSSPageBreakAvoid,
+    CSSPageBreakLeft,
+    CSSPageBreakRight
+}
+
+#[deriving_eq]
+pub enum CSSPageBreakInside {
+    CSSPageBreakInsideAuto,
+    CSSPageBreakInsideAvoid
+}
+
+/// The `size` of a page box, from CSS Paged Media Level 3
+#[deriving_eq]
+pub enum CSSPageSize {
+    CSSPageSizeAuto,
+    CSSPageSizePortrait,
+    CSSPageSizeLandscape,
+    /// Width and height. Named sizes like `A4` are converted to these
+    CSSPageSizeLength(Length, Length)
+}
+
 // CSS 2.1, Section 14 - Colors and Backgrounds
 
 #[deriving_eq]
@@ -202,11 +245,13 @@ pub enum CSSBackgroundColor {
     CSSBackgroundColorTransparent
 }
 
+#[deriving_eq]
 pub enum CSSBackgroundImage {
     CSSBackgroundUri(Url),
     CSSBackgroundImageNone
 }
 
+#[deriving_eq]
 pub enum CSSBackgroundRepeat {
     CSSBackgroundRepeatRepeat,
     CSSBackgroundRepeatRepeatX,
@@ -214,11 +259,13 @@ pub enum CSSBackgroundRepeat {
     CSSBackgroundRepeatNoRepeat
 }
 
+#[deriving_eq]
 pub enum CSSBackgroundAttachment {
     CSSBackgroundAttachmentScroll,
     CSSBackgroundAttachmentFixed
 }
 
+#[deriving_eq]
 pub enum CSSBackgroundPosition {
     CSSBackgroundPositionPercentage(float),
     CSSBackgroundPositionLength(Length),
@@ -279,6 +326,7 @@ pub enum CSSTextAlign {
     CSSTextAlignJustify
 }
 
+#[deriving_eq]
 pub enum CSSTextDecoration {
     CSSTextDecorationNone,
     CSSTextDecorationUnderline,
@@ -287,6 +335,7 @@ pub enum CSSTextDecoration {
     CSSTextDecorationBlink
 }
 
+#[deriving_eq]
 pub enum CSSTextTransform {
     CSSTextTransformCapitalize,
     CSSTextTransformUppercase,